@@ -6,15 +6,21 @@ This script uses the Criterion crate to measure the performance of:
 - process_susceptible: Whether a susceptible cell becomes infected
 - process_infected: Whether an infected cell recovers
 - step_grid: One full update of the simulation grid
+- step_strategies: step_grid_with_rng vs step_grid_tiled vs step_grid_parallel across grid sizes, all seeded
+- count_states: cell-by-cell vs byte-table state counting on a large grid
+- packing: the same step on the 2-bit packed Grid vs one byte per cell
+- float_precision: step_grid_with_rng (f64) vs step_grid_f32
 
 This is part of my first Rust project for learning systems-level simulation and performance profiling.
 */
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 // Import your modules
-use SIR_Model::utils::grid::{Grid, HealthState, Cell};
-use SIR_Model::utils::maths::SirParams;
-use SIR_Model::utils::simulation::{count_infected_neighbors, process_susceptible, process_infected, step_grid};
+use SIR_Model::utils::grid::{Grid, HealthState, Neighborhood};
+use SIR_Model::utils::maths::{count_states, count_states_fast, SirParams};
+use SIR_Model::utils::simulation::{count_infected_neighbors, process_susceptible, process_infected, step_grid, step_grid_f32, step_grid_parallel, step_grid_tiled, step_grid_with_rng};
 
 
 fn dummy_params() -> SirParams {
@@ -24,6 +30,7 @@ fn dummy_params() -> SirParams {
         dt: 1.0,
         i_ratio: 0.1,
         s_ratio: 0.9,
+        ..SirParams::default()
     }
 }
 
@@ -35,7 +42,7 @@ fn benchmark_count_infected_neighbors(c: &mut Criterion) {
     let grid = dummy_grid();
     c.bench_function("count_infected_neighbors", |b| {
         b.iter(|| {
-            count_infected_neighbors(black_box(&grid), black_box(25), black_box(25))
+            count_infected_neighbors(black_box(&grid), black_box(25), black_box(25), Neighborhood::Moore)
         })
     });
 }
@@ -43,18 +50,20 @@ fn benchmark_count_infected_neighbors(c: &mut Criterion) {
 fn benchmark_process_susceptible(c: &mut Criterion) {
     let grid = dummy_grid();
     let params = dummy_params();
+    let mut rng = rand::thread_rng();
     c.bench_function("process_susceptible", |b| {
         b.iter(|| {
-            process_susceptible(black_box(&grid), black_box(25), black_box(25), black_box(&params))
+            process_susceptible(black_box(&grid), black_box(25), black_box(25), black_box(&params), &mut rng)
         })
     });
 }
 
 fn benchmark_process_infected(c: &mut Criterion) {
     let params = dummy_params();
+    let mut rng = rand::thread_rng();
     c.bench_function("process_infected", |b| {
         b.iter(|| {
-            process_infected(black_box(&params))
+            process_infected(black_box(&params), &mut rng)
         })
     });
 }
@@ -69,11 +78,194 @@ fn benchmark_step_grid(c: &mut Criterion) {
     });
 }
 
+// Each iteration steps a fresh clone of the same seeded grid with the same seed, so every
+// strategy sees identical input and draws and results are stable run to run
+fn benchmark_step_strategies(c: &mut Criterion) {
+    let params = dummy_params();
+    let mut group = c.benchmark_group("step_strategies");
+    group.sample_size(10);
+    for size in [100, 500, 1000] {
+        let grid = Grid::init_seeded(size, size, &params, 42);
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_with_input(BenchmarkId::new("step_grid_with_rng", size), &grid, |b, grid| {
+            b.iter_batched(
+                || (grid.clone(), StdRng::seed_from_u64(42)),
+                |(mut g, mut rng)| step_grid_with_rng(&mut g, &params, &mut rng),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("step_grid_tiled", size), &grid, |b, grid| {
+            b.iter(|| step_grid_tiled(black_box(grid), &params, 42, 0, 50, 50).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("step_grid_parallel", size), &grid, |b, grid| {
+            b.iter_batched(|| grid.clone(), |mut g| step_grid_parallel(&mut g, &params, 42, 0), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+fn benchmark_count_states(c: &mut Criterion) {
+    let grid = Grid::init_seeded(1000, 1000, &dummy_params(), 42);
+    let mut group = c.benchmark_group("count_states");
+    group.throughput(Throughput::Elements(1_000_000));
+    group.bench_function("count_states", |b| b.iter(|| count_states(black_box(&grid))));
+    group.bench_function("count_states_fast", |b| b.iter(|| count_states_fast(black_box(&grid))));
+    group.finish();
+}
+
+// Cell storage the packing benchmark steps through; everything else is shared
+trait CellStore: Clone {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn read(&self, idx: usize) -> HealthState;
+    fn write(&mut self, idx: usize, state: HealthState);
+}
+
+impl CellStore for Grid {
+    fn width(&self) -> usize {
+        self.grid_x
+    }
+
+    fn height(&self) -> usize {
+        self.grid_y
+    }
+
+    fn read(&self, idx: usize) -> HealthState {
+        Grid::read(self, idx)
+    }
+
+    fn write(&mut self, idx: usize, state: HealthState) {
+        Grid::write(self, idx, state)
+    }
+}
+
+// One state per byte, no shifting or masking
+#[derive(Clone)]
+struct ByteGrid {
+    width: usize,
+    height: usize,
+    cells: Vec<HealthState>,
+}
+
+impl ByteGrid {
+    fn from_grid(grid: &Grid) -> Self {
+        let cells = (0..grid.grid_x * grid.grid_y).map(|idx| grid.read(idx)).collect();
+        ByteGrid { width: grid.grid_x, height: grid.grid_y, cells }
+    }
+}
+
+impl CellStore for ByteGrid {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn read(&self, idx: usize) -> HealthState {
+        self.cells[idx]
+    }
+
+    fn write(&mut self, idx: usize, state: HealthState) {
+        self.cells[idx] = state;
+    }
+}
+
+// Plain Moore-neighborhood SIR step shared by both representations
+fn step_store<S: CellStore>(grid: &mut S, params: &SirParams, rng: &mut impl Rng) {
+    let current = grid.clone();
+    let (width, height) = (current.width(), current.height());
+    for y in 0..height {
+        for x in 0..width {
+            let idx = y * width + x;
+            let updated = match current.read(idx) {
+                HealthState::Susceptible => {
+                    let mut infected = 0;
+                    for &(dx, dy) in Neighborhood::Moore.offsets() {
+                        let (nx, ny) = (x as isize + dx, y as isize + dy);
+                        if nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < height
+                            && current.read(ny as usize * width + nx as usize) == HealthState::Infected
+                        {
+                            infected += 1;
+                        }
+                    }
+                    if rng.r#gen::<f64>() < params.beta * params.dt * infected as f64 / 8.0 {
+                        HealthState::Infected
+                    } else {
+                        HealthState::Susceptible
+                    }
+                }
+                HealthState::Infected if rng.r#gen::<f64>() < params.gamma * params.dt => HealthState::Recovered,
+                state => state,
+            };
+            grid.write(idx, updated);
+        }
+    }
+}
+
+// Same seeded grid and RNG for both, so only the storage differs; throughput is cells/sec
+fn benchmark_packing(c: &mut Criterion) {
+    let params = dummy_params();
+    let mut group = c.benchmark_group("packing");
+    group.sample_size(10);
+    for size in [100, 500, 1000, 2000] {
+        let packed = Grid::init_seeded(size, size, &params, 42);
+        let bytes = ByteGrid::from_grid(&packed);
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_with_input(BenchmarkId::new("two_bit", size), &packed, |b, grid| {
+            b.iter_batched(
+                || (grid.clone(), StdRng::seed_from_u64(7)),
+                |(mut g, mut rng)| step_store(&mut g, &params, &mut rng),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("byte_per_cell", size), &bytes, |b, grid| {
+            b.iter_batched(
+                || (grid.clone(), StdRng::seed_from_u64(7)),
+                |(mut g, mut rng)| step_store(&mut g, &params, &mut rng),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// Identical seeded grid and RNG seed; only the precision of the cell math differs
+fn benchmark_float_precision(c: &mut Criterion) {
+    let params = dummy_params();
+    let mut group = c.benchmark_group("float_precision");
+    group.sample_size(10);
+    for size in [500, 2000] {
+        let grid = Grid::init_seeded(size, size, &params, 42);
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_with_input(BenchmarkId::new("f64", size), &grid, |b, grid| {
+            b.iter_batched(
+                || (grid.clone(), StdRng::seed_from_u64(7)),
+                |(mut g, mut rng)| step_grid_with_rng(&mut g, &params, &mut rng),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("f32", size), &grid, |b, grid| {
+            b.iter_batched(
+                || (grid.clone(), StdRng::seed_from_u64(7)),
+                |(mut g, mut rng)| step_grid_f32(&mut g, &params, &mut rng),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     benchmark_count_infected_neighbors,
     benchmark_process_susceptible,
     benchmark_process_infected,
-    benchmark_step_grid
+    benchmark_step_grid,
+    benchmark_step_strategies,
+    benchmark_count_states,
+    benchmark_packing,
+    benchmark_float_precision
 );
 criterion_main!(benches);
@@ -0,0 +1,9 @@
+#![allow(non_snake_case)] // crate is published as `SIR_Model`
+
+pub mod utils;
+
+#[cfg(feature = "pyo3")]
+pub mod python;
+
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
@@ -1,16 +1,9 @@
-﻿mod utils {
-    pub mod grid;
-    pub mod maths;
-    pub mod simulation;
-}
-
-use crate::utils::grid::{Grid, HealthState, tile_grid};
-use crate::utils::maths::{SirParams, count_states};
-use crate::utils::simulation::{step_grid, step_grid_tiled};
+﻿use SIR_Model::utils::grid::Grid;
+use SIR_Model::utils::maths::SirParams;
+use SIR_Model::utils::simulation::Simulation;
 
 // Time code execution
 use std::time::Instant;
-use rayon::prelude::*;
 
 fn main() {
 
@@ -22,41 +15,43 @@ fn main() {
         dt: 1.0,           // Time step (days)
         i_ratio: 0.01,     // 1% initially infected
         s_ratio: 1.0,      // All others are susceptible
+        ..SirParams::default()
     };
 
+    if !params.epidemic_feasible() {
+        println!("⚠️ These parameters rule out an epidemic (beta, dt or i_ratio is zero).");
+    }
+
     // 2. Initialize grid using SirParams
-    let mut grid = Grid::init(100, 100, &params);
+    let grid = Grid::init(100, 100, &params);
+    let mut sim = Simulation::new(grid, params);
 
     // 3. Run simulation loop
     
-    let mut day = 0;
     loop {
-        let stats = count_states(&grid);
         /*
+        let stats = count_states(&sim.grid);
         println!(
             "Day {:3}: Susceptible = {:5}, Infected = {:5}, Recovered = {:5}",
-            day, stats.susceptible, stats.infected, stats.recovered
+            sim.day(), stats.susceptible, stats.infected, stats.recovered
         );
         */
-        if stats.infected == 0 {
+        if !sim.grid.any_infected() {
             println!("✅ Infection has died out. Simulation complete.");
             break;
         }
 
 
-        step_grid(&mut grid, &params)
-;       // Parallelie approach for very large grids
-        //grid = step_grid_tiled(&grid, &params, 25, 25);
-       
-
-        day += 1;
+        sim.step();
+        // Parallelie approach for very large grids
+        //sim.grid = step_grid_tiled(&sim.grid, &sim.params, 25, 25);
 
     };
     
     let elapsed = start_time.elapsed(); // Stop timing
     println!(
         "⏱️ Simulation completed in {:.2?} ({} days)",
-        elapsed, day
+        elapsed, sim.day()
     );
 
 
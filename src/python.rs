@@ -0,0 +1,81 @@
+//! Python bindings, built as the `sir_model` extension module with maturin
+//! when the `pyo3` feature is enabled.
+// pyo3 0.22's macro expansions trip edition-2024 and clippy lints.
+#![allow(unsafe_op_in_unsafe_fn, clippy::useless_conversion)]
+use numpy::{PyArray1, PyArray2, PyArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::utils::grid::Grid;
+use crate::utils::maths::SirParams;
+use crate::utils::simulation::Simulation;
+
+/// A seeded simulation driven from Python.
+#[pyclass(name = "PySimulation", unsendable)]
+pub struct PySimulation {
+    sim: Simulation,
+}
+
+#[pymethods]
+impl PySimulation {
+    #[new]
+    fn new(width: usize, height: usize, beta: f64, gamma: f64, dt: f64, i_ratio: f64, seed: u64) -> PyResult<Self> {
+        let params = SirParams { beta, gamma, dt, i_ratio, ..SirParams::default() };
+        let grid = Grid::try_init_with_rng(width, height, &params, &mut StdRng::seed_from_u64(seed))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let sim = Simulation::try_new(grid, params).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PySimulation { sim: sim.with_seed(seed) })
+    }
+
+    /// Advance one day and return the new counts as a dict.
+    fn step<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.sim.step();
+        let stats = self.sim.history.days.last().copied().expect("history records every step");
+        let counts = PyDict::new_bound(py);
+        counts.set_item("susceptible", stats.susceptible)?;
+        counts.set_item("infected", stats.infected)?;
+        counts.set_item("recovered", stats.recovered)?;
+        Ok(counts)
+    }
+
+    /// The grid as a (height, width) uint8 array of state codes (0 = S, 1 = I, 2 = R, 3 = empty).
+    fn grid_as_numpy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        let grid = &self.sim.grid;
+        PyArray1::from_vec_bound(py, grid.to_dense()).reshape([grid.grid_y, grid.grid_x])
+    }
+}
+
+#[pymodule]
+fn sir_model(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySimulation>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_python_step_case1() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut sim = PySimulation::new(10, 10, 0.3, 0.1, 1.0, 0.1, 42).unwrap();
+            let counts = sim.step(py).unwrap();
+            let total: usize = ["susceptible", "infected", "recovered"]
+                .iter()
+                .map(|key| counts.get_item(key).unwrap().unwrap().extract::<usize>().unwrap())
+                .sum();
+            assert_eq!(total, 100);
+        });
+    }
+
+    #[test]
+    fn test_python_new_case1() {
+        pyo3::prepare_freethreaded_python();
+        assert!(PySimulation::new(10, 10, -0.3, 0.1, 1.0, 0.1, 42).is_err());
+        assert!(PySimulation::new(10, 10, 0.3, f64::NAN, 1.0, 0.1, 42).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+﻿use crate::utils::grid::GridError;
+
+/// Errors returned by the crate's fallible public APIs.
+#[derive(Debug)]
+pub enum SirError {
+    /// A grid could not be created, loaded or combined with another.
+    Grid(GridError),
+    /// Coordinates that fall outside the grid.
+    OutOfBounds { x: usize, y: usize, width: usize, height: usize },
+    /// A `SirParams` field is outside its allowed range.
+    InvalidParam { name: &'static str, value: f64 },
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// A saved run file is malformed or truncated.
+    InvalidFile(serde_json::Error),
+    /// An image file could not be read or decoded.
+    #[cfg(feature = "image")]
+    Image(image::ImageError),
+}
+
+impl std::fmt::Display for SirError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SirError::Grid(err) => write!(f, "{}", err),
+            SirError::OutOfBounds { x, y, width, height } => write!(
+                f,
+                "({}, {}) is outside the {}x{} grid",
+                x, y, width, height
+            ),
+            SirError::InvalidParam { name, value } => write!(f, "Invalid value {} for {}", value, name),
+            SirError::Io(err) => write!(f, "I/O error: {}", err),
+            SirError::InvalidFile(err) => write!(f, "Invalid run file: {}", err),
+            #[cfg(feature = "image")]
+            SirError::Image(err) => write!(f, "Image error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SirError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SirError::Grid(err) => Some(err),
+            SirError::Io(err) => Some(err),
+            SirError::InvalidFile(err) => Some(err),
+            #[cfg(feature = "image")]
+            SirError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<GridError> for SirError {
+    fn from(err: GridError) -> Self {
+        SirError::Grid(err)
+    }
+}
+
+#[cfg(feature = "image")]
+impl From<image::ImageError> for SirError {
+    fn from(err: image::ImageError) -> Self {
+        SirError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for SirError {
+    fn from(err: std::io::Error) -> Self {
+        SirError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::grid::{Grid, HealthState, MAX_CELLS};
+    use crate::utils::maths::{InfectiousPeriod, SirParams};
+    use crate::utils::simulation::Simulation;
+
+    #[test]
+    fn test_error_grid_case1() {
+        let err = Grid::try_init(MAX_CELLS, 2, &SirParams::default()).unwrap_err();
+        assert!(matches!(err, SirError::Grid(GridError::TooLarge { .. })));
+
+        let grid = Grid::init(4, 4, &SirParams::default());
+        let params = SirParams { beta_field: Some(vec![0.3; 15]), ..SirParams::default() };
+        let err = Simulation::try_new(grid, params).err().unwrap();
+        assert!(matches!(err, SirError::Grid(GridError::LengthMismatch { expected: 16, found: 15 })));
+    }
+
+    #[test]
+    fn test_error_out_of_bounds_case1() {
+        let grid = Grid::init(4, 3, &SirParams { i_ratio: 0.0, ..SirParams::default() });
+        assert_eq!(grid.try_state_at(3, 2).unwrap(), HealthState::Susceptible);
+        let err = grid.try_state_at(4, 0).unwrap_err();
+        assert!(matches!(err, SirError::OutOfBounds { x: 4, y: 0, width: 4, height: 3 }));
+        assert_eq!(err.to_string(), "(4, 0) is outside the 4x3 grid");
+    }
+
+    #[test]
+    fn test_error_invalid_param_case1() {
+        assert!(SirParams::default().validate().is_ok());
+        let cases = [
+            (SirParams { gamma: -0.1, ..SirParams::default() }, "gamma"),
+            (SirParams { dt: 0.0, ..SirParams::default() }, "dt"),
+            (SirParams { beta: f64::NAN, ..SirParams::default() }, "beta"),
+            (SirParams { i_ratio: 0.6, r_ratio: 0.6, ..SirParams::default() }, "i_ratio + r_ratio"),
+            (SirParams { mobility: 2.0, ..SirParams::default() }, "mobility"),
+            (SirParams { radius: 0, ..SirParams::default() }, "radius"),
+            (SirParams { beta_field: Some(vec![0.3, -0.1]), ..SirParams::default() }, "beta_field"),
+            (SirParams { beta_field: Some(vec![f64::NAN]), ..SirParams::default() }, "beta_field"),
+            (
+                SirParams { infectious_period: InfectiousPeriod::Gamma { shape: 0.0, mean: 5.0 }, ..SirParams::default() },
+                "infectious_period shape",
+            ),
+            (
+                SirParams { infectious_period: InfectiousPeriod::Gamma { shape: 2.0, mean: -1.0 }, ..SirParams::default() },
+                "infectious_period mean",
+            ),
+            (
+                SirParams { infectious_period: InfectiousPeriod::Gamma { shape: 2.0, mean: f64::NAN }, ..SirParams::default() },
+                "infectious_period mean",
+            ),
+        ];
+        for (params, field) in cases {
+            match params.validate() {
+                Err(SirError::InvalidParam { name, .. }) => assert_eq!(name, field),
+                other => panic!("expected InvalidParam for {}, got {:?}", field, other),
+            }
+        }
+        let grid = Grid::init(2, 2, &SirParams::default());
+        let err = Simulation::try_new(grid, SirParams { dt: -1.0, ..SirParams::default() }).err().unwrap();
+        assert!(matches!(err, SirError::InvalidParam { name: "dt", .. }));
+    }
+
+    #[test]
+    fn test_error_io_case1() {
+        let missing = std::env::temp_dir().join(format!("sir_model_{}_missing.bin", std::process::id()));
+        let err = Grid::load_bin(&missing).unwrap_err();
+        assert!(matches!(&err, SirError::Io(io) if io.kind() == std::io::ErrorKind::NotFound));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
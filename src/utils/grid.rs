@@ -1,49 +1,381 @@
-﻿use crate::utils::maths::SirParams;
-use rand::Rng;
-use std::mem::size_of;
+﻿use crate::utils::error::SirError;
+use crate::utils::maths::SirParams;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
 
-/// Two-bit encoding for three health states.
+/// Two-bit encoding for the health states.
+/// `Empty` marks uninhabited cells (water, walls) that never take part in the epidemic.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum HealthState {
     Susceptible = 0,
     Infected    = 1,
     Recovered   = 2,
+    Empty       = 3,
 }
 
+impl HealthState {
+    /// One-character label used by `Grid::debug_dump`: S, I, R, or '.' for Empty.
+    pub fn symbol(self) -> char {
+        match self {
+            HealthState::Susceptible => 'S',
+            HealthState::Infected => 'I',
+            HealthState::Recovered => 'R',
+            HealthState::Empty => '.',
+        }
+    }
+}
+
+/// Which surrounding cells count as neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Neighborhood {
+    /// All 8 surrounding cells, including diagonals.
+    #[default]
+    Moore,
+    /// Only the 4 orthogonally adjacent cells.
+    VonNeumann,
+    /// The 6 cells of a hexagonal lattice stored as "odd-r" offset rows:
+    /// odd rows sit half a cell to the right of even rows.
+    Hex,
+}
+
+impl Neighborhood {
+    /// Neighbor count of a fully-surrounded cell; the divisor of the infection probability.
+    pub fn max_neighbors(&self) -> usize {
+        self.offsets().len()
+    }
+
+    /// Neighbor count of a fully-surrounded cell when reaching `radius` cells out.
+    pub fn max_neighbors_within(&self, radius: usize) -> usize {
+        match self {
+            Neighborhood::Moore => (2 * radius + 1).pow(2) - 1,
+            Neighborhood::VonNeumann => 2 * radius * (radius + 1),
+            Neighborhood::Hex => 3 * radius * (radius + 1),
+        }
+    }
+
+    /// Offsets of every neighbor within `radius`: Chebyshev distance for Moore,
+    /// Manhattan distance for Von Neumann. Radius 1 yields the same set as `offsets`.
+    /// For `Hex` these are the offsets from an even row; see `offsets_within_at`.
+    pub fn offsets_within(&self, radius: usize) -> impl Iterator<Item = (isize, isize)> {
+        self.offsets_within_at(radius, 0)
+    }
+
+    /// `offsets_within` for a cell in row `y`, which matters only for `Hex`
+    /// (hex distance depends on the row's parity).
+    pub fn offsets_within_at(&self, radius: usize, y: usize) -> impl Iterator<Item = (isize, isize)> {
+        let neighborhood = *self;
+        let r = radius as isize;
+        (-r..=r)
+            .flat_map(move |dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(move |&(dx, dy)| {
+                (dx, dy) != (0, 0)
+                    && match neighborhood {
+                        Neighborhood::Moore => true,
+                        Neighborhood::VonNeumann => dx.abs() + dy.abs() <= r,
+                        Neighborhood::Hex => hex_distance(dx, dy, y) <= r,
+                    }
+            })
+    }
+
+    /// Distance from a cell in row `y` to the cell at offset (dx, dy):
+    /// Euclidean on the square lattice, steps between hexes for `Hex`.
+    pub fn distance_at(&self, dx: isize, dy: isize, y: usize) -> f64 {
+        match self {
+            Neighborhood::Hex => hex_distance(dx, dy, y) as f64,
+            _ => ((dx * dx + dy * dy) as f64).sqrt(),
+        }
+    }
+
+    /// Relative (dx, dy) offsets of the neighbors.
+    /// For `Hex` these are the offsets from an even row; see `offsets_at`.
+    pub fn offsets(&self) -> &'static [(isize, isize)] {
+        self.offsets_at(0)
+    }
+
+    /// Relative (dx, dy) offsets of the neighbors of a cell in row `y`.
+    pub fn offsets_at(&self, y: usize) -> &'static [(isize, isize)] {
+        match self {
+            Neighborhood::Moore => &[
+                (-1, -1), (0, -1), (1, -1),
+                (-1, 0),           (1, 0),
+                (-1, 1),  (0, 1),  (1, 1),
+            ],
+            Neighborhood::VonNeumann => &[(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighborhood::Hex if y.is_multiple_of(2) => &[
+                (-1, -1), (0, -1),
+                (-1, 0),           (1, 0),
+                (-1, 1),  (0, 1),
+            ],
+            Neighborhood::Hex => &[
+                (0, -1), (1, -1),
+                (-1, 0),          (1, 0),
+                (0, 1),  (1, 1),
+            ],
+        }
+    }
+}
+
+/// Hex steps from a cell in row `y` to the cell at offset (dx, dy), with rows
+/// in "odd-r" layout. Converts both to axial coordinates relative to the cell.
+fn hex_distance(dx: isize, dy: isize, y: usize) -> isize {
+    let ny = (y % 2) as isize + dy;
+    let dq = dx - (ny - (ny & 1)) / 2;
+    (dq.abs() + dy.abs() + (dq + dy).abs()) / 2
+}
+
+/// First bytes of a binary grid file written by `Grid::save_bin`.
+pub const BIN_MAGIC: [u8; 4] = *b"SIRG";
+/// Current version of the binary grid format.
+pub const BIN_VERSION: u8 = 1;
+/// Magic, version byte, then `grid_x` and `grid_y` as little-endian `u32`s.
+const BIN_HEADER_LEN: usize = 4 + 1 + 4 + 4;
+
+/// Bytes (4 cells each) filled per RNG stream by `Grid::init_parallel`.
+pub const INIT_CHUNK_BYTES: usize = 1 << 16;
+
+/// Bits that pack one cell's `HealthState`. All four states, Empty included,
+/// fit in two, so this is the only packing a `Grid` uses.
+pub const BITS_PER_CELL: usize = 2;
+
+/// Largest number of cells a grid may hold.
+pub const MAX_CELLS: usize = 1_000_000_000;
+
+/// Grids with both sides at most this long get coordinate labels in `Grid::debug_dump`.
+const DUMP_COORDS_MAX_SIDE: usize = 64;
+
+/// Reasons a grid cannot be created or combined with another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GridError {
+    /// `grid_x * grid_y` does not fit in a `usize`.
+    DimensionOverflow,
+    /// The grid would hold more than `limit` cells.
+    TooLarge { requested: usize, limit: usize },
+    /// A requested region does not fit inside the grid.
+    OutOfBounds,
+    /// Two grids that must share dimensions do not.
+    DimensionMismatch { expected: (usize, usize), found: (usize, usize) },
+    /// A per-cell buffer does not hold exactly one entry per cell.
+    LengthMismatch { expected: usize, found: usize },
+    /// A dense buffer holds a byte that is not a `HealthState`.
+    InvalidState { index: usize, value: u8 },
+    /// An initial ratio is outside [0, 1], or `i_ratio + r_ratio` exceeds 1.
+    InvalidRatios,
+    /// An image pixel is not within tolerance of any palette colour.
+    UnmatchedColor { index: usize, color: [u8; 3] },
+    /// A tile dimension is zero.
+    InvalidTileSize { width: usize, height: usize },
+}
+
+impl std::fmt::Display for GridError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GridError::DimensionOverflow => write!(f, "Grid dimensions overflowed"),
+            GridError::TooLarge { requested, limit } => write!(
+                f,
+                "Grid too large: {} cells. Limit is {}.",
+                requested, limit
+            ),
+            GridError::OutOfBounds => write!(f, "Region exceeds grid bounds"),
+            GridError::DimensionMismatch { expected, found } => write!(
+                f,
+                "Grid dimensions differ: expected {}x{}, found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            GridError::LengthMismatch { expected, found } => write!(
+                f,
+                "Expected {} cells, found {}",
+                expected, found
+            ),
+            GridError::InvalidState { index, value } => write!(
+                f,
+                "Invalid state {} at cell {}",
+                value, index
+            ),
+            GridError::InvalidRatios => write!(
+                f,
+                "Initial ratios must lie in [0, 1], with i_ratio + r_ratio at most 1"
+            ),
+            GridError::UnmatchedColor { index, color } => write!(
+                f,
+                "Colour {:?} at cell {} matches no palette colour",
+                color, index
+            ),
+            GridError::InvalidTileSize { width, height } => write!(
+                f,
+                "Invalid tile size {}x{}: both sides must be at least 1",
+                width, height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
 /// Flat, bit-packed grid: 2 bits per cell, 4 cells per byte.
+///
+/// Invariant: when the cell count is not a multiple of 4, the unused high bits
+/// of the last byte are zero, so whole-byte operations (`PartialEq`, bulk
+/// counting, serialization) can treat the buffer as fully defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Grid {
     pub grid_x: usize,
     pub grid_y: usize,
     pub cells: Vec<u8>,  // 2 bits per cell packed into bytes
 }
 impl Grid {
-    /// Initialize a new grid, randomly infecting according to params.i_ratio.
+    /// Initialize a new grid, randomly infecting according to params.i_ratio
+    /// and making cells Recovered according to params.initial_recovered_ratio().
+    /// Panics if the dimensions are rejected by `try_init`.
     pub fn init(grid_x: usize, grid_y: usize, params: &SirParams) -> Self {
-        const MAX_CELLS: usize = 1_000_000_000;
+        Self::try_init(grid_x, grid_y, params).expect("Invalid grid dimensions")
+    }
+
+    /// Fallible version of `init`: rejects dimensions that overflow or exceed `MAX_CELLS`.
+    pub fn try_init(grid_x: usize, grid_y: usize, params: &SirParams) -> Result<Self, SirError> {
+        Self::try_init_with_rng(grid_x, grid_y, params, &mut rand::thread_rng())
+    }
+
+    /// Like `init`, but reproducible: the random infections are drawn from a `StdRng` seeded with `seed`.
+    pub fn init_seeded(grid_x: usize, grid_y: usize, params: &SirParams, seed: u64) -> Self {
+        Self::try_init_with_rng(grid_x, grid_y, params, &mut StdRng::seed_from_u64(seed))
+            .expect("Invalid grid dimensions")
+    }
+
+    /// Fallible initialization drawing the random infections from `rng`.
+    pub fn try_init_with_rng(grid_x: usize, grid_y: usize, params: &SirParams, rng: &mut impl Rng) -> Result<Self, SirError> {
+        let size = Self::checked_size(grid_x, grid_y)?;
+        Self::check_ratios(params)?;
+        // 4 cells per byte
+        let byte_len = size.div_ceil(4);
+        let mut cells = vec![0u8; byte_len];
+        Self::fill_random(&mut cells, size, params, rng);
+        let mut grid = Grid { grid_x, grid_y, cells };
+        grid.normalize_padding();
+        Ok(grid)
+    }
+
+    /// All-Susceptible grid, drawing nothing from an RNG. Fill it with `reset`.
+    /// Panics if the dimensions are rejected by `try_init`.
+    pub fn zeroed(grid_x: usize, grid_y: usize) -> Self {
+        let size = Self::checked_size(grid_x, grid_y).expect("Invalid grid dimensions");
+        Grid { grid_x, grid_y, cells: vec![0; size.div_ceil(4)] }
+    }
+
+    /// Re-randomize every cell in place, keeping the dimensions and buffer.
+    /// Leaves the grid exactly as `try_init_with_rng` would build it from the
+    /// same `rng` state, so ensembles can reuse one grid per replicate.
+    pub fn reset(&mut self, params: &SirParams, rng: &mut impl Rng) -> Result<(), SirError> {
+        Self::check_ratios(params)?;
+        Self::fill_random(&mut self.cells, self.grid_x * self.grid_y, params, rng);
+        self.normalize_padding();
+        Ok(())
+    }
+
+    /// Change the dimensions to `new_x` x `new_y`. Cells inside both the old and
+    /// new bounds keep their state at the same (x, y); new cells are set to `fill`
+    /// and cells outside the new bounds are dropped.
+    pub fn resize(&mut self, new_x: usize, new_y: usize, fill: HealthState) -> Result<(), SirError> {
+        let size = Self::checked_size(new_x, new_y)?;
+        let mut resized = Grid { grid_x: new_x, grid_y: new_y, cells: vec![(fill as u8) * 0b0101_0101; size.div_ceil(4)] };
+        resized.normalize_padding();
+        for y in 0..self.grid_y.min(new_y) {
+            for x in 0..self.grid_x.min(new_x) {
+                resized.write(resized.get_index(x, y), self.read(self.get_index(x, y)));
+            }
+        }
+        *self = resized;
+        Ok(())
+    }
+
+    /// Like `init_seeded`, but fills chunks of `INIT_CHUNK_BYTES` concurrently.
+    /// Chunk `k` draws from its own `StdRng` seeded with
+    /// `seed ^ k * 0x9E37_79B9_7F4A_7C15`, so the result does not depend on the
+    /// thread count, and a grid that fits in one chunk matches `init_seeded`.
+    pub fn init_parallel(grid_x: usize, grid_y: usize, params: &SirParams, seed: u64) -> Self {
+        Self::try_init_parallel(grid_x, grid_y, params, seed).expect("Invalid grid dimensions")
+    }
+
+    /// Fallible version of `init_parallel`.
+    pub fn try_init_parallel(grid_x: usize, grid_y: usize, params: &SirParams, seed: u64) -> Result<Self, SirError> {
+        use rayon::prelude::*;
+
+        let size = Self::checked_size(grid_x, grid_y)?;
+        Self::check_ratios(params)?;
+        let mut cells = vec![0u8; size.div_ceil(4)];
+        cells.par_chunks_mut(INIT_CHUNK_BYTES).enumerate().for_each(|(chunk, bytes)| {
+            let mut rng = StdRng::seed_from_u64(seed ^ (chunk as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let first_cell = chunk * INIT_CHUNK_BYTES * 4;
+            Self::fill_random(bytes, (size - first_cell).min(bytes.len() * 4), params, &mut rng);
+        });
+        let mut grid = Grid { grid_x, grid_y, cells };
+        grid.normalize_padding();
+        Ok(grid)
+    }
+
+    /// Cell count for the dimensions, rejecting overflow and grids above `MAX_CELLS`.
+    fn checked_size(grid_x: usize, grid_y: usize) -> Result<usize, GridError> {
         let size = grid_x.checked_mul(grid_y)
-            .expect("Grid dimensions overflowed");
+            .ok_or(GridError::DimensionOverflow)?;
 
         if size > MAX_CELLS {
-            panic!(
-                "Grid too large: {}x{} = {} cells. Limit is {}.",
-                grid_x, grid_y, size, MAX_CELLS
-            );
+            return Err(GridError::TooLarge { requested: size, limit: MAX_CELLS });
         }
-        // 4 cells per byte
-        let byte_len = (size + 3) / 4;
-        let mut cells = vec![0u8; byte_len];
-        let mut rng = rand::thread_rng();
-        for idx in 0..size {
+        Ok(size)
+    }
+
+    /// Reject initial ratios that don't describe a valid split of the population.
+    fn check_ratios(params: &SirParams) -> Result<(), GridError> {
+        let unit = 0.0..=1.0;
+        let ratios = [params.i_ratio, params.s_ratio, params.r_ratio];
+        if ratios.iter().all(|ratio| unit.contains(ratio)) && params.i_ratio + params.r_ratio <= 1.0 {
+            Ok(())
+        } else {
+            Err(GridError::InvalidRatios)
+        }
+    }
+
+    /// Make each of the first `count` cells of `cells` Infected with probability
+    /// `params.i_ratio`, Recovered with probability `params.initial_recovered_ratio()`,
+    /// else Susceptible.
+    fn fill_random(cells: &mut [u8], count: usize, params: &SirParams, rng: &mut impl Rng) {
+        let recovered_below = params.i_ratio + params.initial_recovered_ratio();
+        for idx in 0..count {
             let roll: f64 = rng.r#gen();
             let state = if roll < params.i_ratio {
                 HealthState::Infected
+            } else if roll < recovered_below {
+                HealthState::Recovered
             } else {
                 HealthState::Susceptible
             };
-            Self::write_state(&mut cells, idx, state);
+            Self::write_state(cells, idx, state);
         }
-        Grid { grid_x, grid_y, cells }
+    }
+
+    /// Like `init`, but cells whose `mask` entry is false are left `Empty`.
+    /// `mask` is indexed like the grid cells and must cover every cell.
+    pub fn init_with_mask(grid_x: usize, grid_y: usize, params: &SirParams, mask: &[bool]) -> Self {
+        let mut grid = Self::init(grid_x, grid_y, params);
+        assert_eq!(mask.len(), grid_x * grid_y, "Mask size does not match grid");
+        for (idx, &habitable) in mask.iter().enumerate() {
+            if !habitable {
+                grid.write(idx, HealthState::Empty);
+            }
+        }
+        grid
+    }
+
+    /// Like `init` with no random infections; exactly the cells at `seeds` start infected.
+    /// Panics if a seed lies outside the grid.
+    pub fn init_with_seeds(grid_x: usize, grid_y: usize, params: &SirParams, seeds: &[(usize, usize)]) -> Self {
+        let mut grid = Self::init(grid_x, grid_y, &SirParams { i_ratio: 0.0, ..params.clone() });
+        for &(x, y) in seeds {
+            assert!(x < grid_x && y < grid_y, "Seed ({}, {}) is outside the grid", x, y);
+            grid.write(grid.get_index(x, y), HealthState::Infected);
+        }
+        grid
     }
 
     /// Internal helper: write directly to raw cell buffer
@@ -59,21 +391,84 @@ impl Grid {
         y * self.grid_x + x
     }
 
-    /// Return the 8 neighbors' coordinates (still allocates Vec here).
-    pub fn get_neighbors(&self, x: usize, y: usize, buffer: &mut [(usize, usize)]) -> usize {
+    /// Coordinates of the in-bounds Moore neighbors (up to 8). Allocates; hot
+    /// paths should use `get_neighbors_into` or `neighbors`.
+    pub fn get_neighbors(&self, x: usize, y: usize) -> Vec<(usize, usize)> {
+        let mut buffer = [(0, 0); 8];
+        let count = self.get_neighbors_into(x, y, &mut buffer);
+        buffer[..count].to_vec()
+    }
+
+    /// Write the in-bounds Moore neighbors' coordinates (up to 8) into `buffer`,
+    /// returning how many were written.
+    pub fn get_neighbors_into(&self, x: usize, y: usize, buffer: &mut [(usize, usize)]) -> usize {
+        self.get_neighbors_in(x, y, Neighborhood::Moore, buffer)
+    }
+
+    /// Iterator over the in-bounds Moore neighbors' coordinates, backed by a
+    /// fixed array instead of a caller buffer or heap allocation.
+    pub fn neighbors(&self, x: usize, y: usize) -> NeighborIter {
+        let mut buffer = [(0, 0); 8];
+        let len = self.get_neighbors_into(x, y, &mut buffer);
+        NeighborIter { buffer, len, pos: 0 }
+    }
+
+    /// Write the in-bounds neighbors' coordinates for `neighborhood` into `buffer`,
+    /// returning how many were written.
+    pub fn get_neighbors_in(&self, x: usize, y: usize, neighborhood: Neighborhood, buffer: &mut [(usize, usize)]) -> usize {
         let mut count = 0;
-        for dy in -1..=1 {
-            for dx in -1..=1 {
-                if dx == 0 && dy == 0 { continue; }
+        for &(dx, dy) in neighborhood.offsets_at(y) {
+            let nx = x as isize + dx;
+            let ny = y as isize + dy;
+            if nx >= 0 && nx < self.grid_x as isize && ny >= 0 && ny < self.grid_y as isize {
+                buffer[count] = (nx as usize, ny as usize);
+                count += 1
+            }
+        }
+        count
+    }
+
+    /// (infected, total) counts over the in-bounds neighbors of (x, y) in one pass,
+    /// stepping linear indices from the cell's own index instead of going
+    /// through neighbor coordinates.
+    pub fn infected_neighbor_count(&self, x: usize, y: usize, neighborhood: Neighborhood) -> (usize, usize) {
+        let idx = self.get_index(x, y) as isize;
+        let (mut infected, mut total) = (0, 0);
+        for &(dx, dy) in neighborhood.offsets_at(y) {
+            let nx = x as isize + dx;
+            let ny = y as isize + dy;
+            if nx >= 0 && nx < self.grid_x as isize && ny >= 0 && ny < self.grid_y as isize {
+                total += 1;
+                if self.read((idx + dy * self.grid_x as isize + dx) as usize) == HealthState::Infected {
+                    infected += 1;
+                }
+            }
+        }
+        (infected, total)
+    }
+
+    /// All in-bounds cells within Chebyshev distance `radius` of (x, y).
+    pub fn get_neighbors_radius(&self, x: usize, y: usize, radius: usize) -> Vec<(usize, usize)> {
+        Neighborhood::Moore
+            .offsets_within(radius)
+            .filter_map(|(dx, dy)| {
                 let nx = x as isize + dx;
                 let ny = y as isize + dy;
                 if nx >= 0 && nx < self.grid_x as isize && ny >= 0 && ny < self.grid_y as isize {
-                    buffer[count] = (nx as usize, ny as usize);
-                    count += 1
+                    Some((nx as usize, ny as usize))
+                } else {
+                    None
                 }
-            }
+            })
+            .collect()
+    }
+
+    /// State of the cell at (x, y), or `SirError::OutOfBounds`.
+    pub fn try_state_at(&self, x: usize, y: usize) -> Result<HealthState, SirError> {
+        if x >= self.grid_x || y >= self.grid_y {
+            return Err(SirError::OutOfBounds { x, y, width: self.grid_x, height: self.grid_y });
         }
-        count
+        Ok(self.read(self.get_index(x, y)))
     }
 
     /// Read the state at linear index.
@@ -84,7 +479,7 @@ impl Grid {
             0 => HealthState::Susceptible,
             1 => HealthState::Infected,
             2 => HealthState::Recovered,
-            _ => unreachable!("Invalid state bits"),
+            _ => HealthState::Empty,
         }
     }
 
@@ -96,16 +491,321 @@ impl Grid {
         self.cells[byte] = (self.cells[byte] & mask) | ((state as u8) << shift);
     }
 
-    /// Prints approximate memory usage: 2 bits/cell packed in `cells.len()` bytes.
-    pub fn get_grid_size(&self) -> (usize, usize, usize) {
-        let bits_per_cell = 2;
-        let heap_bytes = self.cells.len();
-        let struct_bytes = std::mem::size_of::<Self>();
-        println!("Bits per cell: {}", bits_per_cell);
-        println!("Total heap usage: {} bytes (~{:.2} MB)", heap_bytes, heap_bytes as f64 / (1024.0*1024.0));
-        println!("Grid struct size: {} bytes", struct_bytes);
-        (bits_per_cell, heap_bytes, struct_bytes)
+    /// Set every cell to `state`, a whole byte (four cells) at a time.
+    pub fn fill(&mut self, state: HealthState) {
+        self.cells.fill((state as u8) * 0b0101_0101);
+        self.normalize_padding();
+    }
+
+    /// Zero the padding bits past the last cell in the final byte.
+    fn normalize_padding(&mut self) {
+        let used = (self.grid_x * self.grid_y) % 4;
+        if used != 0
+            && let Some(last) = self.cells.last_mut()
+        {
+            *last &= (1u8 << (used * 2)) - 1;
+        }
+    }
+
+    /// Every cell's ((x, y), state) in row-major order.
+    pub fn iter_cells(&self) -> impl Iterator<Item = ((usize, usize), HealthState)> + '_ {
+        (0..self.grid_x * self.grid_y)
+            .map(move |idx| ((idx % self.grid_x, idx / self.grid_x), self.read(idx)))
+    }
+
+    /// Copy the `w`x`h` rectangle starting at (x0, y0) into a new, independent grid.
+    /// Unlike `Tile`, the result owns its cells.
+    pub fn subgrid(&self, x0: usize, y0: usize, w: usize, h: usize) -> Result<Grid, SirError> {
+        let fits = |start: usize, len: usize, max: usize| start.checked_add(len).is_some_and(|end| end <= max);
+        if !fits(x0, w, self.grid_x) || !fits(y0, h, self.grid_y) {
+            return Err(GridError::OutOfBounds.into());
+        }
+        let mut sub = Grid { grid_x: w, grid_y: h, cells: vec![0; (w * h).div_ceil(4)] };
+        for y in 0..h {
+            for x in 0..w {
+                let state = self.read(self.get_index(x0 + x, y0 + y));
+                sub.write(sub.get_index(x, y), state);
+            }
+        }
+        Ok(sub)
+    }
+
+    /// One byte per cell (`HealthState as u8`, so 0-3) in row-major order.
+    pub fn to_dense(&self) -> Vec<u8> {
+        (0..self.grid_x * self.grid_y).map(|idx| self.read(idx) as u8).collect()
+    }
+
+    /// Inverse of `to_dense`: pack one byte per cell into a `width` x `height` grid.
+    pub fn from_dense(width: usize, height: usize, dense: &[u8]) -> Result<Grid, SirError> {
+        let size = Self::checked_size(width, height)?;
+        if dense.len() != size {
+            return Err(GridError::LengthMismatch { expected: size, found: dense.len() }.into());
+        }
+        let mut cells = vec![0u8; size.div_ceil(4)];
+        for (idx, &value) in dense.iter().enumerate() {
+            let state = match value {
+                0 => HealthState::Susceptible,
+                1 => HealthState::Infected,
+                2 => HealthState::Recovered,
+                3 => HealthState::Empty,
+                _ => return Err(GridError::InvalidState { index: idx, value }.into()),
+            };
+            Self::write_state(&mut cells, idx, state);
+        }
+        Ok(Grid { grid_x: width, grid_y: height, cells })
+    }
+
+    /// Grid from an image file, one cell per pixel, each taking the state whose
+    /// `palette` colour is nearest. Pixels further than `tolerance` (Euclidean
+    /// RGB distance) from every palette colour are an error; alpha is ignored.
+    #[cfg(feature = "image")]
+    pub fn from_image(path: impl AsRef<std::path::Path>, palette: &crate::utils::render::Palette, tolerance: f64) -> Result<Grid, SirError> {
+        let image = image::open(path)?.into_rgb8();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let dense = image
+            .pixels()
+            .enumerate()
+            .map(|(index, pixel)| {
+                palette
+                    .nearest_state(pixel.0, tolerance)
+                    .map(|state| state as u8)
+                    .ok_or(GridError::UnmatchedColor { index, color: pixel.0 })
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        Grid::from_dense(width, height, &dense)
+    }
+
+    /// New grid with cell (x, y) of the result taken from `source(x, y)`.
+    fn remap(&self, grid_x: usize, grid_y: usize, source: impl Fn(usize, usize) -> (usize, usize)) -> Grid {
+        let mut out = Grid { grid_x, grid_y, cells: vec![0; (grid_x * grid_y).div_ceil(4)] };
+        for y in 0..grid_y {
+            for x in 0..grid_x {
+                let (sx, sy) = source(x, y);
+                out.write(out.get_index(x, y), self.read(self.get_index(sx, sy)));
+            }
+        }
+        out
+    }
+
+    /// The grid rotated 90° clockwise; `grid_x` and `grid_y` swap.
+    pub fn rotate90(&self) -> Grid {
+        let height = self.grid_y;
+        self.remap(self.grid_y, self.grid_x, |x, y| (y, height - 1 - x))
+    }
+
+    /// The grid mirrored left to right.
+    pub fn flip_horizontal(&self) -> Grid {
+        let width = self.grid_x;
+        self.remap(self.grid_x, self.grid_y, |x, y| (width - 1 - x, y))
+    }
+
+    /// The grid mirrored top to bottom.
+    pub fn flip_vertical(&self) -> Grid {
+        let height = self.grid_y;
+        self.remap(self.grid_x, self.grid_y, |x, y| (x, height - 1 - y))
+    }
+
+    /// Smaller grid where each cell takes the most common state of a
+    /// `factor` x `factor` block (blocks on the right and bottom edges may be
+    /// partial). Ties go to Infected, then Recovered, Susceptible, Empty, so
+    /// sparse infections stay visible. Panics if `factor` is 0.
+    pub fn downsample(&self, factor: usize) -> Grid {
+        assert!(factor > 0, "Downsample factor must be at least 1");
+        let (grid_x, grid_y) = (self.grid_x.div_ceil(factor), self.grid_y.div_ceil(factor));
+        let mut out = Grid { grid_x, grid_y, cells: vec![0; (grid_x * grid_y).div_ceil(4)] };
+        // Tie-break rank, indexed by `HealthState as usize`
+        const PRIORITY: [u8; 4] = [1, 3, 2, 0];
+        for by in 0..grid_y {
+            for bx in 0..grid_x {
+                let mut counts = [0usize; 4];
+                for y in by * factor..((by + 1) * factor).min(self.grid_y) {
+                    for x in bx * factor..((bx + 1) * factor).min(self.grid_x) {
+                        counts[self.read(self.get_index(x, y)) as usize] += 1;
+                    }
+                }
+                let majority = [HealthState::Susceptible, HealthState::Infected, HealthState::Recovered, HealthState::Empty]
+                    .into_iter()
+                    .max_by_key(|&state| (counts[state as usize], PRIORITY[state as usize]))
+                    .expect("four candidate states");
+                out.write(out.get_index(bx, by), majority);
+            }
+        }
+        out
+    }
+
+    /// Cells whose state differs in `other`, as (linear index, state in `other`).
+    /// Applying these writes to `self` reproduces `other`.
+    pub fn diff(&self, other: &Grid) -> Result<Vec<(usize, HealthState)>, SirError> {
+        if (self.grid_x, self.grid_y) != (other.grid_x, other.grid_y) {
+            return Err(GridError::DimensionMismatch {
+                expected: (self.grid_x, self.grid_y),
+                found: (other.grid_x, other.grid_y),
+            }
+            .into());
+        }
+        Ok((0..self.grid_x * self.grid_y)
+            .filter_map(|idx| {
+                let state = other.read(idx);
+                (self.read(idx) != state).then_some((idx, state))
+            })
+            .collect())
     }
+
+    /// Panic with a readable message, including `debug_dump` for small grids,
+    /// unless the cell at (x, y) is in state `expected`.
+    #[track_caller]
+    pub fn assert_state_at(&self, x: usize, y: usize, expected: HealthState) {
+        assert!(x < self.grid_x && y < self.grid_y, "({}, {}) is outside the {}x{} grid", x, y, self.grid_x, self.grid_y);
+        let found = self.read(self.get_index(x, y));
+        if found != expected {
+            let dump = if self.grid_x.max(self.grid_y) <= DUMP_COORDS_MAX_SIDE { self.debug_dump() } else { String::new() };
+            panic!("cell ({}, {}): expected {:?}, found {:?}\n{}", x, y, expected, found, dump);
+        }
+    }
+
+    /// One line per row of `HealthState::symbol`s. Grids with both sides at
+    /// most `DUMP_COORDS_MAX_SIDE` get a header of x coordinates (last digit)
+    /// and each row is prefixed with its y coordinate.
+    pub fn debug_dump(&self) -> String {
+        let labelled = self.grid_x.max(self.grid_y) <= DUMP_COORDS_MAX_SIDE;
+        let label_width = self.grid_y.saturating_sub(1).to_string().len();
+        let mut out = String::new();
+        if labelled {
+            out.push_str(&" ".repeat(label_width + 1));
+            out.extend((0..self.grid_x).map(|x| char::from(b'0' + (x % 10) as u8)));
+            out.push('\n');
+        }
+        for y in 0..self.grid_y {
+            if labelled {
+                out.push_str(&format!("{:>width$} ", y, width = label_width));
+            }
+            out.extend((0..self.grid_x).map(|x| self.read(self.get_index(x, y)).symbol()));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Write the grid in the compact binary format: `BIN_MAGIC`, `BIN_VERSION`,
+    /// `grid_x` and `grid_y` as little-endian `u32`, then the packed cell bytes.
+    pub fn save_bin(&self, path: impl AsRef<std::path::Path>) -> Result<(), SirError> {
+        let too_wide = |_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Grid dimension exceeds u32");
+        let grid_x = u32::try_from(self.grid_x).map_err(too_wide)?;
+        let grid_y = u32::try_from(self.grid_y).map_err(too_wide)?;
+        let mut bytes = Vec::with_capacity(BIN_HEADER_LEN + self.cells.len());
+        bytes.extend_from_slice(&BIN_MAGIC);
+        bytes.push(BIN_VERSION);
+        bytes.extend_from_slice(&grid_x.to_le_bytes());
+        bytes.extend_from_slice(&grid_y.to_le_bytes());
+        bytes.extend_from_slice(&self.cells);
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Read a grid written by `save_bin`. Fails with an `InvalidData` I/O error if
+    /// the magic, version or dimensions don't match the file's contents.
+    pub fn load_bin(path: impl AsRef<std::path::Path>) -> Result<Grid, SirError> {
+        let invalid = |msg: String| SirError::from(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < BIN_HEADER_LEN || bytes[..4] != BIN_MAGIC {
+            return Err(invalid("Not a binary grid file".to_string()));
+        }
+        if bytes[4] != BIN_VERSION {
+            return Err(invalid(format!("Unsupported binary grid version {}", bytes[4])));
+        }
+        let grid_x = u32::from_le_bytes(bytes[5..9].try_into().unwrap()) as usize;
+        let grid_y = u32::from_le_bytes(bytes[9..13].try_into().unwrap()) as usize;
+        let size = grid_x
+            .checked_mul(grid_y)
+            .ok_or_else(|| invalid(GridError::DimensionOverflow.to_string()))?;
+        if size > MAX_CELLS {
+            return Err(invalid(GridError::TooLarge { requested: size, limit: MAX_CELLS }.to_string()));
+        }
+        let cells = &bytes[BIN_HEADER_LEN..];
+        if cells.len() != size.div_ceil(4) {
+            return Err(invalid(format!(
+                "{}x{} grid needs {} cell bytes, file has {}",
+                grid_x, grid_y, size.div_ceil(4), cells.len()
+            )));
+        }
+        let mut grid = Grid { grid_x, grid_y, cells: cells.to_vec() };
+        grid.normalize_padding();
+        Ok(grid)
+    }
+
+    /// Count the cells in a single `state`.
+    pub fn count_state(&self, state: HealthState) -> usize {
+        (0..self.grid_x * self.grid_y)
+            .filter(|&idx| self.read(idx) == state)
+            .count()
+    }
+
+    /// True if any cell is infected; stops at the first one found.
+    pub fn any_infected(&self) -> bool {
+        (0..self.grid_x * self.grid_y).any(|idx| self.read(idx) == HealthState::Infected)
+    }
+
+    /// What `memory_usage` will report for a `grid_x` x `grid_y` grid, without
+    /// allocating it. Fails for dimensions `try_init` would reject.
+    pub fn memory_for(grid_x: usize, grid_y: usize) -> Result<GridMemory, SirError> {
+        let size = Self::checked_size(grid_x, grid_y)?;
+        Ok(GridMemory {
+            bits_per_cell: BITS_PER_CELL,
+            heap_bytes: (size * BITS_PER_CELL).div_ceil(8),
+            struct_bytes: std::mem::size_of::<Self>(),
+        })
+    }
+
+    /// Approximate memory usage: `BITS_PER_CELL` bits/cell packed in `cells.len()` bytes.
+    pub fn memory_usage(&self) -> GridMemory {
+        GridMemory {
+            bits_per_cell: BITS_PER_CELL,
+            heap_bytes: self.cells.len(),
+            struct_bytes: std::mem::size_of::<Self>(),
+        }
+    }
+
+    /// Prints the figures from `memory_usage` to stdout.
+    pub fn print_memory_usage(&self) {
+        let memory = self.memory_usage();
+        println!("Bits per cell: {}", memory.bits_per_cell);
+        println!("Total heap usage: {} bytes (~{:.2} MB)", memory.heap_bytes, memory.heap_bytes as f64 / (1024.0*1024.0));
+        println!("Grid struct size: {} bytes", memory.struct_bytes);
+    }
+}
+
+/// Neighbor coordinates returned by `Grid::neighbors`.
+#[derive(Debug, Clone)]
+pub struct NeighborIter {
+    buffer: [(usize, usize); 8],
+    len: usize,
+    pos: usize,
+}
+
+impl Iterator for NeighborIter {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.len {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.buffer[self.pos - 1])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.pos;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for NeighborIter {}
+
+/// Memory footprint of a `Grid`, as reported by `Grid::memory_usage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridMemory {
+    pub bits_per_cell: usize,
+    pub heap_bytes: usize,
+    pub struct_bytes: usize,
 }
 
 pub struct Tile<'a> {
@@ -135,26 +835,23 @@ impl<'a> Tile<'a> {
         Some(self.grid.read(idx))
     }
 
-    pub fn get_neighbors_healthstates(&self, x: usize, y: usize, buffer: &mut [Option<HealthState>; 8]) -> usize {
+    pub fn get_neighbors_healthstates(&self, x: usize, y: usize, neighborhood: Neighborhood, buffer: &mut [Option<HealthState>; 8]) -> usize {
         let mut count = 0;
-        // Loop over the 3x3 grid centered at (x, y)
-        for dy in -1..=1 {
-            for dx in -1..=1 {
-                if dx == 0 && dy == 0 { continue; }
-                 // Compute neighbor coordinates (may be negative)
-                let nx = x as isize + dx;
-                let ny = y as isize + dy;
+        // Loop over the neighborhood centered at (x, y); hex rows depend on the global row
+        for &(dx, dy) in neighborhood.offsets_at(self.origin_y + y) {
+            // Compute neighbor coordinates (may be negative)
+            let nx = x as isize + dx;
+            let ny = y as isize + dy;
 
-                // Try to convert isize -> usize safely (only works if >= 0)
-                if let (Some(nx), Some(ny)) =
-                    (nx.try_into().ok(), ny.try_into().ok())
-                {
-                    // Use get_state to retrieve the neighbor’s health state (returns Option)
-                    if let Some(state) = self.get_state(nx, ny) {
-                        // If the neighbor exists and is within bounds, store its state
-                        buffer[count] = Some(state);
-                        count += 1;
-                    }
+            // Try to convert isize -> usize safely (only works if >= 0)
+            if let (Some(nx), Some(ny)) =
+                (nx.try_into().ok(), ny.try_into().ok())
+            {
+                // Use get_state to retrieve the neighbor’s health state (returns Option)
+                if let Some(state) = self.get_state(nx, ny) {
+                    // If the neighbor exists and is within bounds, store its state
+                    buffer[count] = Some(state);
+                    count += 1;
                 }
             }
         }
@@ -162,11 +859,32 @@ impl<'a> Tile<'a> {
     }
 }
 
-pub fn tile_grid(grid: &Grid, tile_width: usize, tile_height: usize) -> Vec<Tile> {
+/// Smallest tile side `optimal_tile_size` picks, unless the grid itself is smaller.
+pub const MIN_TILE_SIDE: usize = 16;
+
+/// Tile dimensions for `tile_grid` giving roughly one squarish tile per rayon
+/// thread, never smaller than `MIN_TILE_SIDE` (or the grid) on a side.
+pub fn optimal_tile_size(grid_x: usize, grid_y: usize) -> (usize, usize) {
+    let (grid_x, grid_y) = (grid_x.max(1), grid_y.max(1));
+    let threads = rayon::current_num_threads().max(1);
+    // Split the threads between the axes in proportion to the grid's aspect ratio
+    let tiles_x = ((threads as f64 * grid_x as f64 / grid_y as f64).sqrt().round() as usize).clamp(1, threads);
+    let tiles_y = threads.div_ceil(tiles_x);
+    let side = |len: usize, tiles: usize| len.div_ceil(tiles).max(MIN_TILE_SIDE).min(len);
+    (side(grid_x, tiles_x), side(grid_y, tiles_y))
+}
+
+/// Split `grid` into row-major tiles of `tile_width` x `tile_height`. Tiles on
+/// the right and bottom edges are clipped to the grid, so a tile larger than
+/// the grid gives a single tile covering all of it. Rejects a zero dimension.
+pub fn tile_grid(grid: &Grid, tile_width: usize, tile_height: usize) -> Result<Vec<Tile<'_>>, SirError> {
+    if tile_width == 0 || tile_height == 0 {
+        return Err(GridError::InvalidTileSize { width: tile_width, height: tile_height }.into());
+    }
     let mut tiles = Vec::new();
 
-    let num_tiles_x = (grid.grid_x + tile_width - 1) / tile_width;
-    let num_tiles_y = (grid.grid_y + tile_height - 1) / tile_height;
+    let num_tiles_x = grid.grid_x.div_ceil(tile_width);
+    let num_tiles_y = grid.grid_y.div_ceil(tile_height);
 
     for tile_y in 0..num_tiles_y {
         for tile_x in 0..num_tiles_x {
@@ -187,7 +905,7 @@ pub fn tile_grid(grid: &Grid, tile_width: usize, tile_height: usize) -> Vec<Tile
         }
     }
 
-    tiles
+    Ok(tiles)
 }
 
 
@@ -195,7 +913,14 @@ pub fn tile_grid(grid: &Grid, tile_width: usize, tile_height: usize) -> Vec<Tile
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::maths::SirParams;
+    use crate::utils::maths::{SirParams, count_states, count_states_fast};
+
+    fn grid_error<T>(result: Result<T, SirError>) -> Option<GridError> {
+        match result {
+            Err(SirError::Grid(err)) => Some(err),
+            _ => None,
+        }
+    }
 
     fn dummy_params(i_ratio: f64) -> SirParams {
         SirParams {
@@ -204,6 +929,7 @@ mod tests {
             dt: 1.0,
             i_ratio,
             s_ratio: 1.0, // Fully susceptible for now
+            ..SirParams::default()
         }
     }
 
@@ -213,18 +939,301 @@ mod tests {
         let grid = Grid::init(10, 5, &params);
         assert_eq!(grid.grid_x, 10);
         assert_eq!(grid.grid_y, 5);
-        assert_eq!(grid.cells.len(), (10 * 5 + 3) / 4); // expect 13 bytes
+        assert_eq!(grid.cells.len(), (10 * 5_usize).div_ceil(4)); // expect 13 bytes
+    }
+
+    #[test]
+    fn test_grid_init_seeded_case1() {
+        let params = dummy_params(0.3);
+        let a = Grid::init_seeded(50, 50, &params, 17);
+        let b = Grid::init_seeded(50, 50, &params, 17);
+        let c = Grid::init_seeded(50, 50, &params, 18);
+        assert!(a == b);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn test_grid_try_init_case1() {
+        let params = dummy_params(0.0);
+        let result = Grid::try_init(usize::MAX, 2, &params);
+        assert_eq!(grid_error(result), Some(GridError::DimensionOverflow));
+    }
+
+    #[test]
+    fn test_grid_try_init_case2() {
+        let params = dummy_params(0.0);
+        let result = Grid::try_init(100_000, 100_000, &params);
+        assert_eq!(
+            grid_error(result),
+            Some(GridError::TooLarge { requested: 10_000_000_000, limit: MAX_CELLS })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid grid dimensions")]
+    fn test_grid_init_case2() {
+        let params = dummy_params(0.0);
+        Grid::init(usize::MAX, 2, &params);
+    }
+
+    #[test]
+    fn test_grid_count_state_case1() {
+        let params = dummy_params(0.0);
+        let mut grid = Grid::init(5, 5, &params);
+        grid.write(grid.get_index(2, 3), HealthState::Infected);
+        assert_eq!(grid.count_state(HealthState::Infected), 1);
+        assert_eq!(grid.count_state(HealthState::Susceptible), 24);
+        assert_eq!(grid.count_state(HealthState::Recovered), 0);
+    }
+
+    #[test]
+    fn test_grid_iter_cells_case1() {
+        let params = dummy_params(0.0);
+        let mut grid = Grid::init(3, 2, &params);
+        grid.write(grid.get_index(2, 1), HealthState::Recovered);
+        let cells: Vec<_> = grid.iter_cells().collect();
+        assert_eq!(cells.len(), 6);
+        let coords: Vec<_> = cells.iter().map(|&(coord, _)| coord).collect();
+        assert_eq!(coords, vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]);
+        assert_eq!(cells[5].1, HealthState::Recovered);
+        assert_eq!(cells[0].1, HealthState::Susceptible);
+    }
+
+    #[test]
+    fn test_grid_subgrid_case1() {
+        let params = dummy_params(0.0);
+        let mut grid = Grid::init(4, 4, &params);
+        grid.write(grid.get_index(2, 2), HealthState::Infected);
+        grid.write(grid.get_index(3, 2), HealthState::Recovered);
+        grid.write(grid.get_index(3, 3), HealthState::Empty);
+
+        let sub = grid.subgrid(2, 2, 2, 2).unwrap();
+        assert_eq!((sub.grid_x, sub.grid_y), (2, 2));
+        assert_eq!(sub.read(sub.get_index(0, 0)), HealthState::Infected);
+        assert_eq!(sub.read(sub.get_index(1, 0)), HealthState::Recovered);
+        assert_eq!(sub.read(sub.get_index(0, 1)), HealthState::Susceptible);
+        assert_eq!(sub.read(sub.get_index(1, 1)), HealthState::Empty);
+    }
+
+    #[test]
+    fn test_grid_subgrid_case2() {
+        let params = dummy_params(0.0);
+        let grid = Grid::init(4, 4, &params);
+        assert_eq!(grid_error(grid.subgrid(3, 0, 2, 2)), Some(GridError::OutOfBounds));
+        assert_eq!(grid_error(grid.subgrid(0, usize::MAX, 1, 1)), Some(GridError::OutOfBounds));
     }
 
     #[test]
-    fn test_grid_get_grid_size_case1() {
+    fn test_grid_fill_case1() {
+        let params = dummy_params(0.0);
+        // 15 cells: the last byte only holds 3 of them
+        let mut grid = Grid::init(5, 3, &params);
+        grid.fill(HealthState::Infected);
+        let stats = count_states(&grid);
+        assert_eq!((stats.susceptible, stats.infected, stats.recovered), (0, 15, 0));
+        assert_eq!(count_states_fast(&grid), stats);
+
+        grid.fill(HealthState::Empty);
+        assert_eq!(grid.count_state(HealthState::Empty), 15);
+        assert_eq!(count_states(&grid).infected, 0);
+    }
+
+    #[test]
+    fn test_grid_normalize_padding_case1() {
+        let params = dummy_params(1.0);
+        // 50 cells in 13 bytes: byte 12 holds cells 48 and 49 in its low 4 bits
+        let mut grid = Grid::init(10, 5, &params);
+        assert_eq!(grid.cells.len(), 13);
+        assert_eq!(grid.cells[12] >> 4, 0);
+
+        grid.fill(HealthState::Empty);
+        assert_eq!(grid.cells[12], 0b0000_1111);
+
+        grid.cells[12] = 0xFF;
+        grid.normalize_padding();
+        assert_eq!(grid.cells[12], 0b0000_1111);
+        assert_eq!(grid.read(49), HealthState::Empty);
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("sir_model_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_grid_save_bin_case1() {
+        let params = dummy_params(0.3);
+        let mut grid = Grid::init(7, 5, &params);
+        grid.write(34, HealthState::Empty);
+        grid.write(3, HealthState::Recovered);
+        let path = temp_path("roundtrip.bin");
+        grid.save_bin(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 13 + 9);
+        let loaded = Grid::load_bin(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, grid);
+    }
+
+    #[test]
+    fn test_grid_load_bin_case1() {
+        let params = dummy_params(0.3);
+        let grid = Grid::init(7, 5, &params);
+        let path = temp_path("corrupt.bin");
+        grid.save_bin(&path).unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[0] ^= 0xFF;
+        std::fs::write(&path, &bytes).unwrap();
+        let magic_err = Grid::load_bin(&path).unwrap_err();
+
+        bytes[0] ^= 0xFF;
+        bytes.pop();
+        std::fs::write(&path, &bytes).unwrap();
+        let length_err = Grid::load_bin(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        for err in [magic_err, length_err] {
+            assert!(matches!(&err, SirError::Io(io) if io.kind() == std::io::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn test_grid_init_parallel_case1() {
+        let params = dummy_params(0.5);
+        // Reference pattern for seed 42: a single chunk, so identical to init_seeded
+        let grid = Grid::init_parallel(6, 2, &params, 42);
+        assert_eq!(grid.cells, vec![64, 5, 5]);
+        assert_eq!(grid, Grid::init_seeded(6, 2, &params, 42));
+    }
+
+    #[test]
+    fn test_grid_init_parallel_case2() {
+        let params = dummy_params(0.5);
+        // The second chunk is filled from its own derived seed
+        let width = INIT_CHUNK_BYTES * 4 + 10;
+        let grid = Grid::init_parallel(width, 1, &params, 42);
+        let tail = Grid::init_seeded(10, 1, &params, 42 ^ 0x9E37_79B9_7F4A_7C15);
+        assert_eq!(grid.cells[INIT_CHUNK_BYTES..], tail.cells[..]);
+        assert_eq!(grid.cells[..INIT_CHUNK_BYTES], Grid::init_seeded(width - 10, 1, &params, 42).cells[..]);
+
+        let large = Grid::init_parallel(1000, 1000, &params, 7);
+        let infected = large.count_state(HealthState::Infected) as f64 / 1_000_000.0;
+        assert!((infected - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_grid_rotate90_case1() {
+        let params = dummy_params(0.0);
+        // 3 wide, 2 tall:
+        //   I S R
+        //   S S E
+        let mut grid = Grid::init(3, 2, &params);
+        grid.write(grid.get_index(0, 0), HealthState::Infected);
+        grid.write(grid.get_index(2, 0), HealthState::Recovered);
+        grid.write(grid.get_index(2, 1), HealthState::Empty);
+
+        // Clockwise:
+        //   S I
+        //   S S
+        //   E R
+        let rotated = grid.rotate90();
+        assert_eq!((rotated.grid_x, rotated.grid_y), (2, 3));
+        assert_eq!(rotated.read(rotated.get_index(1, 0)), HealthState::Infected);
+        assert_eq!(rotated.read(rotated.get_index(1, 2)), HealthState::Recovered);
+        assert_eq!(rotated.read(rotated.get_index(0, 2)), HealthState::Empty);
+        assert_eq!(rotated.count_state(HealthState::Susceptible), 3);
+
+        assert_eq!(rotated.rotate90().rotate90().rotate90(), grid);
+    }
+
+    #[test]
+    fn test_grid_flip_case1() {
+        let params = dummy_params(0.4);
+        let grid = Grid::init(5, 3, &params);
+        let horizontal = grid.flip_horizontal();
+        let vertical = grid.flip_vertical();
+        assert_eq!(horizontal.read(horizontal.get_index(4, 1)), grid.read(grid.get_index(0, 1)));
+        assert_eq!(vertical.read(vertical.get_index(1, 0)), grid.read(grid.get_index(1, 2)));
+        assert_eq!(horizontal.flip_horizontal(), grid);
+        assert_eq!(vertical.flip_vertical(), grid);
+        // Both flips together are a half turn
+        assert_eq!(horizontal.flip_vertical(), grid.rotate90().rotate90());
+    }
+
+    #[test]
+    fn test_grid_to_dense_case1() {
+        let params = dummy_params(0.3);
+        let mut grid = Grid::init(7, 3, &params);
+        grid.write(5, HealthState::Recovered);
+        grid.write(20, HealthState::Empty);
+        let dense = grid.to_dense();
+        assert_eq!(dense.len(), 21);
+        assert_eq!((dense[5], dense[20]), (2, 3));
+        let back = Grid::from_dense(7, 3, &dense).unwrap();
+        assert_eq!(back.cells, grid.cells);
+    }
+
+    #[test]
+    fn test_grid_from_dense_case1() {
+        assert_eq!(
+            grid_error(Grid::from_dense(2, 2, &[0, 1, 2])),
+            Some(GridError::LengthMismatch { expected: 4, found: 3 })
+        );
+        assert_eq!(
+            grid_error(Grid::from_dense(2, 2, &[0, 1, 4, 0])),
+            Some(GridError::InvalidState { index: 2, value: 4 })
+        );
+    }
+
+    #[test]
+    fn test_grid_diff_case1() {
+        let params = dummy_params(0.0);
+        let grid = Grid::init(5, 3, &params);
+        let mut next = grid.clone();
+        next.write(2, HealthState::Infected);
+        next.write(14, HealthState::Recovered);
+        assert_eq!(grid.diff(&next).unwrap(), vec![(2, HealthState::Infected), (14, HealthState::Recovered)]);
+        assert!(grid.diff(&grid).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_grid_diff_case2() {
+        let params = dummy_params(0.0);
+        let grid = Grid::init(5, 3, &params);
+        let other = Grid::init(3, 5, &params);
+        assert_eq!(
+            grid_error(grid.diff(&other)),
+            Some(GridError::DimensionMismatch { expected: (5, 3), found: (3, 5) })
+        );
+    }
+
+    #[test]
+    fn test_grid_any_infected_case1() {
+        let params = dummy_params(0.0);
+        let mut grid = Grid::init(5, 5, &params);
+        assert!(!grid.any_infected());
+        grid.write(grid.get_index(4, 4), HealthState::Infected);
+        assert!(grid.any_infected());
+    }
+
+    #[test]
+    fn test_grid_memory_usage_case1() {
         let params = dummy_params(0.0);
         let grid = Grid::init(100, 100, &params);
-        let (bits_per_cell, heap_size, struct_size) = grid.get_grid_size();
+        let memory = grid.memory_usage();
     
-        assert_eq!(bits_per_cell, 2);
-        assert_eq!(heap_size, 2500); // 10000 cells / 4 = 2500 bytes
-        assert!(struct_size > 0); // or check against actual value
+        assert_eq!(memory.bits_per_cell, 2);
+        assert_eq!(memory.heap_bytes, 2500); // 10000 cells / 4 = 2500 bytes
+        assert!(memory.struct_bytes > 0); // or check against actual value
+    }
+
+    #[test]
+    fn test_grid_memory_usage_case2() {
+        // 50 cells do not fill the last byte; it is still counted whole
+        let grid = Grid::init(10, 5, &dummy_params(0.0));
+        let memory = grid.memory_usage();
+        assert_eq!(memory.bits_per_cell, BITS_PER_CELL);
+        assert_eq!(memory.heap_bytes, (50 * BITS_PER_CELL).div_ceil(8));
+        assert_eq!(memory.heap_bytes, 13);
     }
 
     #[test]
@@ -271,6 +1280,87 @@ mod tests {
         assert!(neighbors.contains(&(1, 11)));
     }
 
+    #[test]
+    fn test_grid_get_neighbors_in_case1() {
+        let params = dummy_params(0.0);
+        let grid = Grid::init(20, 20, &params);
+        let mut buffer = [(0, 0); 8];
+        let count = grid.get_neighbors_in(10, 10, Neighborhood::VonNeumann, &mut buffer);
+        let neighbors = &buffer[..count];
+        assert_eq!(neighbors.len(), 4);
+        assert!(neighbors.contains(&(10, 9)));
+        assert!(neighbors.contains(&(9, 10)));
+        assert!(neighbors.contains(&(11, 10)));
+        assert!(neighbors.contains(&(10, 11)));
+    }
+
+    #[test]
+    fn test_grid_get_neighbors_in_case2() {
+        let params = dummy_params(0.0);
+        let grid = Grid::init(20, 20, &params);
+        let mut buffer = [(0, 0); 8];
+
+        // Even row: the neighbors above and below lean left
+        let count = grid.get_neighbors_in(10, 10, Neighborhood::Hex, &mut buffer);
+        let mut neighbors = buffer[..count].to_vec();
+        neighbors.sort();
+        assert_eq!(neighbors, vec![(9, 9), (9, 10), (9, 11), (10, 9), (10, 11), (11, 10)]);
+
+        // Odd row: they lean right
+        let count = grid.get_neighbors_in(10, 11, Neighborhood::Hex, &mut buffer);
+        let mut neighbors = buffer[..count].to_vec();
+        neighbors.sort();
+        assert_eq!(neighbors, vec![(9, 11), (10, 10), (10, 12), (11, 10), (11, 11), (11, 12)]);
+
+        // Boundary cells have fewer
+        assert_eq!(grid.get_neighbors_in(0, 10, Neighborhood::Hex, &mut buffer), 3);
+        assert_eq!(grid.get_neighbors_in(0, 0, Neighborhood::Hex, &mut buffer), 2);
+        assert_eq!(grid.get_neighbors_in(19, 11, Neighborhood::Hex, &mut buffer), 3);
+    }
+
+    #[test]
+    fn test_grid_neighborhood_hex_case1() {
+        assert_eq!(Neighborhood::Hex.max_neighbors(), 6);
+        assert_eq!(Neighborhood::Hex.max_neighbors_within(2), 18);
+        for y in [0, 1] {
+            assert_eq!(Neighborhood::Hex.offsets_within_at(1, y).count(), 6);
+            assert_eq!(Neighborhood::Hex.offsets_within_at(2, y).count(), 18);
+            for &(dx, dy) in Neighborhood::Hex.offsets_at(y) {
+                assert_eq!(Neighborhood::Hex.distance_at(dx, dy, y), 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid_neighborhood_max_neighbors_case1() {
+        assert_eq!(Neighborhood::Moore.max_neighbors(), 8);
+        assert_eq!(Neighborhood::VonNeumann.max_neighbors(), 4);
+        assert_eq!(Neighborhood::Moore.max_neighbors_within(2), 24);
+        assert_eq!(Neighborhood::VonNeumann.max_neighbors_within(2), 12);
+        assert_eq!(Neighborhood::VonNeumann.offsets_within(2).count(), 12);
+    }
+
+    #[test]
+    fn test_grid_get_neighbors_radius_case1() {
+        let params = dummy_params(0.0);
+        let grid = Grid::init(20, 20, &params);
+        let neighbors = grid.get_neighbors_radius(10, 10, 2);
+        assert_eq!(neighbors.len(), 24);
+        assert!(neighbors.contains(&(8, 8)));
+        assert!(neighbors.contains(&(12, 12)));
+        assert!(!neighbors.contains(&(10, 10)));
+    }
+
+    #[test]
+    // Radius 1 is exactly the Moore neighborhood
+    fn test_grid_get_neighbors_radius_case2() {
+        let params = dummy_params(0.0);
+        let grid = Grid::init(20, 20, &params);
+        for &(x, y) in &[(0, 0), (0, 10), (10, 10), (19, 19)] {
+            assert_eq!(grid.get_neighbors_radius(x, y, 1), grid.get_neighbors(x, y));
+        }
+    }
+
     #[test]
     fn test_grid_tile_grid_case1() {
         use crate::utils::maths::SirParams;
@@ -282,13 +1372,14 @@ mod tests {
             dt: 1.0,
             i_ratio: 0.0,
             s_ratio: 1.0,
+            ..SirParams::default()
         };
 
         // 100x100 grid
         let grid = Grid::init(100, 100, &params);
 
         // Tile into 25x25 chunks
-        let tiles = tile_grid(&grid, 25, 25);
+        let tiles = tile_grid(&grid, 25, 25).unwrap();
 
         // Should be 4 x 4 = 16 tiles
         assert_eq!(tiles.len(), 16);
@@ -304,4 +1395,239 @@ mod tests {
         assert_eq!(tiles[4].origin_y, 25);
         
     }
+
+    #[test]
+    fn test_grid_optimal_tile_size_case1() {
+        let threads = rayon::current_num_threads();
+        let (w, h) = optimal_tile_size(1000, 1000);
+        let tiles = 1000usize.div_ceil(w) * 1000usize.div_ceil(h);
+        assert!(tiles * 2 >= threads && tiles <= threads * 2, "{} tiles for {} threads", tiles, threads);
+        assert!(w.max(h) <= 2 * w.min(h), "tile {}x{}", w, h);
+
+        // Small grids are never cut below MIN_TILE_SIDE, or past their own size
+        assert_eq!(optimal_tile_size(3, 3), (3, 3));
+        let (w, h) = optimal_tile_size(40, 1000);
+        assert!(w >= MIN_TILE_SIDE.min(40) && h >= MIN_TILE_SIDE);
+        assert_eq!(optimal_tile_size(0, 0), (1, 1));
+    }
+
+    #[test]
+    fn test_grid_init_with_mask_case1() {
+        let params = dummy_params(1.0);
+        let mask = [true, false, true, false];
+        let grid = Grid::init_with_mask(2, 2, &params, &mask);
+        assert_eq!(grid.read(0), HealthState::Infected);
+        assert_eq!(grid.read(1), HealthState::Empty);
+        assert_eq!(grid.read(2), HealthState::Infected);
+        assert_eq!(grid.read(3), HealthState::Empty);
+    }
+
+    #[test]
+    fn test_grid_debug_dump_case1() {
+        let mut grid = Grid::init(4, 3, &dummy_params(0.0));
+        grid.write(grid.get_index(1, 0), HealthState::Infected);
+        grid.write(grid.get_index(2, 1), HealthState::Recovered);
+        grid.write(grid.get_index(3, 2), HealthState::Empty);
+        assert_eq!(grid.debug_dump(), "  0123\n0 SISS\n1 SSRS\n2 SSS.\n");
+
+        // Larger grids drop the coordinates
+        let wide = Grid::init(DUMP_COORDS_MAX_SIDE + 1, 2, &dummy_params(0.0));
+        let line = "S".repeat(DUMP_COORDS_MAX_SIDE + 1);
+        assert_eq!(wide.debug_dump(), format!("{}\n{}\n", line, line));
+    }
+
+    #[test]
+    fn test_grid_assert_state_at_case1() {
+        let mut grid = Grid::init(3, 2, &dummy_params(0.0));
+        grid.write(grid.get_index(2, 1), HealthState::Infected);
+        grid.assert_state_at(2, 1, HealthState::Infected);
+        grid.assert_state_at(0, 0, HealthState::Susceptible);
+
+        let message = *std::panic::catch_unwind(|| grid.assert_state_at(2, 1, HealthState::Recovered))
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert_eq!(message, "cell (2, 1): expected Recovered, found Infected\n  012\n0 SSS\n1 SSI\n");
+    }
+
+    #[test]
+    fn test_grid_infected_neighbor_count_case1() {
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0));
+        for (x, y) in [(0, 0), (1, 0), (2, 1), (2, 2)] {
+            grid.write(grid.get_index(x, y), HealthState::Infected);
+        }
+        assert_eq!(grid.infected_neighbor_count(1, 1, Neighborhood::Moore), (4, 8));
+        assert_eq!(grid.infected_neighbor_count(1, 1, Neighborhood::VonNeumann), (2, 4));
+        // Corner and edge cells only see their in-bounds neighbors
+        assert_eq!(grid.infected_neighbor_count(0, 2, Neighborhood::Moore), (0, 3));
+        assert_eq!(grid.infected_neighbor_count(2, 0, Neighborhood::Moore), (2, 3));
+    }
+
+    #[test]
+    fn test_grid_init_r_ratio_case1() {
+        let params = SirParams { r_ratio: 0.5, ..dummy_params(0.1) };
+        let grid = Grid::init_seeded(50, 50, &params, 8);
+        let recovered = grid.count_state(HealthState::Recovered) as f64 / 2500.0;
+        let infected = grid.count_state(HealthState::Infected) as f64 / 2500.0;
+        assert!((recovered - 0.5).abs() < 0.05, "recovered {}", recovered);
+        assert!((infected - 0.1).abs() < 0.03, "infected {}", infected);
+    }
+
+    #[test]
+    fn test_grid_init_r_ratio_case2() {
+        for (i_ratio, r_ratio) in [(0.6, 0.5), (0.0, -0.1), (0.0, 1.5)] {
+            let params = SirParams { r_ratio, ..dummy_params(i_ratio) };
+            assert_eq!(grid_error(Grid::try_init(4, 4, &params)), Some(GridError::InvalidRatios));
+            assert_eq!(grid_error(Grid::try_init_parallel(4, 4, &params, 1)), Some(GridError::InvalidRatios));
+        }
+        let all_recovered = Grid::try_init(4, 4, &SirParams { r_ratio: 1.0, ..dummy_params(0.0) }).unwrap();
+        assert_eq!(all_recovered.count_state(HealthState::Recovered), 16);
+    }
+
+    #[test]
+    fn test_grid_neighbors_case1() {
+        let grid = Grid::init(5, 5, &dummy_params(0.0));
+        let neighbors: Vec<(usize, usize)> = grid.neighbors(2, 2).collect();
+        assert_eq!(neighbors, [(1, 1), (2, 1), (3, 1), (1, 2), (3, 2), (1, 3), (2, 3), (3, 3)]);
+
+        let corner = grid.neighbors(0, 0);
+        assert_eq!(corner.len(), 3);
+        assert_eq!(corner.collect::<Vec<_>>(), [(1, 0), (0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_grid_init_s_ratio_case1() {
+        // 10% infected, 60% susceptible: the other 30% start recovered
+        let params = SirParams { s_ratio: 0.6, ..dummy_params(0.1) };
+        assert!((params.initial_recovered_ratio() - 0.3).abs() < 1e-12);
+        let grid = Grid::init_seeded(50, 50, &params, 4);
+        let recovered = grid.count_state(HealthState::Recovered) as f64 / 2500.0;
+        let susceptible = grid.count_state(HealthState::Susceptible) as f64 / 2500.0;
+        assert!((recovered - 0.3).abs() < 0.04, "recovered {}", recovered);
+        assert!((susceptible - 0.6).abs() < 0.04, "susceptible {}", susceptible);
+
+        // s_ratio 1.0 leaves nothing over, so nobody starts recovered
+        assert_eq!(dummy_params(0.1).initial_recovered_ratio(), 0.0);
+        assert_eq!(Grid::init(20, 20, &dummy_params(0.1)).count_state(HealthState::Recovered), 0);
+
+        let invalid = SirParams { s_ratio: 1.5, ..dummy_params(0.1) };
+        assert_eq!(grid_error(Grid::try_init(4, 4, &invalid)), Some(GridError::InvalidRatios));
+    }
+
+    #[test]
+    fn test_grid_downsample_case1() {
+        let mut grid = Grid::init(4, 4, &dummy_params(0.0));
+        // Top left: 3 recovered. Top right: 2 infected vs 2 susceptible.
+        // Bottom left: all susceptible. Bottom right: 1 infected, 1 recovered, 2 empty.
+        for (x, y, state) in [
+            (0, 0, HealthState::Recovered), (1, 0, HealthState::Recovered), (0, 1, HealthState::Recovered),
+            (2, 0, HealthState::Infected), (3, 1, HealthState::Infected),
+            (2, 2, HealthState::Infected), (3, 2, HealthState::Recovered),
+            (2, 3, HealthState::Empty), (3, 3, HealthState::Empty),
+        ] {
+            grid.write(grid.get_index(x, y), state);
+        }
+
+        let small = grid.downsample(2);
+        assert_eq!((small.grid_x, small.grid_y), (2, 2));
+        assert_eq!(small.debug_dump(), "  01\n0 RI\n1 S.\n");
+        assert_eq!(grid.downsample(1), grid);
+    }
+
+    #[test]
+    fn test_grid_downsample_case2() {
+        // Partial edge blocks: 5x3 by 2 gives 3x2
+        let mut grid = Grid::init(5, 3, &dummy_params(0.0));
+        grid.write(grid.get_index(4, 2), HealthState::Infected);
+        let small = grid.downsample(2);
+        assert_eq!((small.grid_x, small.grid_y), (3, 2));
+        small.assert_state_at(2, 1, HealthState::Infected);
+        small.assert_state_at(2, 0, HealthState::Susceptible);
+    }
+
+    #[test]
+    fn test_grid_get_neighbors_into_case1() {
+        let grid = Grid::init(20, 20, &dummy_params(0.0));
+        let mut buffer = [(0, 0); 8];
+        // Interior, corner, top edge and far corner
+        for (x, y, expected) in [(10, 10, 8), (0, 0, 3), (10, 0, 5), (19, 19, 3)] {
+            let count = grid.get_neighbors_into(x, y, &mut buffer);
+            assert_eq!(count, expected, "({}, {})", x, y);
+            assert_eq!(buffer[..count].to_vec(), grid.get_neighbors(x, y));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_grid_from_image_case1() {
+        use crate::utils::render::Palette;
+        // Slightly-off template colours, as left by antialiasing or lossy editing
+        let colors: [[u8; 3]; 6] = [[10, 240, 5], [250, 8, 12], [128, 128, 128], [6, 0, 4], [0, 255, 0], [200, 30, 20]];
+        let mut image = image::RgbImage::new(3, 2);
+        for (pixel, color) in image.pixels_mut().zip(colors) {
+            *pixel = image::Rgb(color);
+        }
+        let path = temp_path("template.png");
+        image.save(&path).unwrap();
+
+        let grid = Grid::from_image(&path, &Palette::template(), 80.0).unwrap();
+        assert_eq!((grid.grid_x, grid.grid_y), (3, 2));
+        let states: Vec<HealthState> = grid.iter_cells().map(|(_, state)| state).collect();
+        use HealthState::*;
+        assert_eq!(states, [Susceptible, Infected, Recovered, Empty, Susceptible, Infected]);
+
+        let err = Grid::from_image(&path, &Palette::template(), 20.0).unwrap_err();
+        assert!(matches!(err, SirError::Grid(GridError::UnmatchedColor { index: 5, color: [200, 30, 20] })));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_grid_reset_case1() {
+        let params = SirParams { r_ratio: 0.2, ..dummy_params(0.1) };
+        let mut grid = Grid::zeroed(13, 7);
+        assert!(grid.iter_cells().all(|(_, state)| state == HealthState::Susceptible));
+        grid.reset(&params, &mut StdRng::seed_from_u64(3)).unwrap();
+        assert_eq!(grid, Grid::init_seeded(13, 7, &params, 3));
+
+        // A used grid, with Empty cells and a different fill, resets just the same
+        grid.fill(HealthState::Empty);
+        let buffer = grid.cells.as_ptr();
+        grid.reset(&params, &mut StdRng::seed_from_u64(9)).unwrap();
+        assert_eq!(grid, Grid::init_seeded(13, 7, &params, 9));
+        assert_eq!(grid.cells.as_ptr(), buffer);
+
+        let bad = SirParams { i_ratio: 1.5, ..params };
+        assert_eq!(grid_error(grid.reset(&bad, &mut StdRng::seed_from_u64(9))), Some(GridError::InvalidRatios));
+    }
+
+    #[test]
+    fn test_grid_tile_grid_case2() {
+        let grid = Grid::init(10, 6, &dummy_params(0.0));
+        assert!(matches!(tile_grid(&grid, 0, 3), Err(SirError::Grid(GridError::InvalidTileSize { width: 0, height: 3 }))));
+        assert!(matches!(tile_grid(&grid, 3, 0), Err(SirError::Grid(GridError::InvalidTileSize { width: 3, height: 0 }))));
+
+        let tiles = tile_grid(&grid, 50, 40).unwrap();
+        assert_eq!(tiles.len(), 1);
+        assert_eq!((tiles[0].origin_x, tiles[0].origin_y, tiles[0].tile_x, tiles[0].tile_y), (0, 0, 10, 6));
+    }
+
+    #[test]
+    fn test_grid_resize_case1() {
+        let mut original = Grid::init_seeded(7, 5, &SirParams { r_ratio: 0.2, s_ratio: 0.5, ..dummy_params(0.3) }, 11);
+        original.write(original.get_index(6, 4), HealthState::Empty);
+        let mut grid = original.clone();
+        grid.resize(13, 9, HealthState::Susceptible).unwrap();
+        assert_eq!((grid.grid_x, grid.grid_y), (13, 9));
+        for ((x, y), state) in grid.iter_cells() {
+            let expected = if x < 7 && y < 5 { original.read(original.get_index(x, y)) } else { HealthState::Susceptible };
+            assert_eq!(state, expected, "cell ({}, {})", x, y);
+        }
+
+        grid.resize(3, 2, HealthState::Empty).unwrap();
+        assert_eq!(grid, original.subgrid(0, 0, 3, 2).unwrap());
+        grid.resize(4, 3, HealthState::Empty).unwrap();
+        assert_eq!(grid.count_state(HealthState::Empty), 6 + original.subgrid(0, 0, 3, 2).unwrap().count_state(HealthState::Empty));
+        assert!(grid.resize(usize::MAX, 2, HealthState::Empty).is_err());
+        assert_eq!((grid.grid_x, grid.grid_y), (4, 3));
+    }
 }
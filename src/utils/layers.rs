@@ -0,0 +1,220 @@
+use rand::Rng;
+use crate::utils::grid::{Grid, HealthState};
+use crate::utils::maths::{AgeBracket, InfectiousnessProfile, SirParams};
+
+/// Per-cell data kept alongside the packed grid, indexed like the cells.
+/// An empty vector means that layer is not in use.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellLayers {
+    /// Steps each infected cell has left before recovering. Only used when
+    /// `params.infectious_period` is timed.
+    pub infection_timers: Vec<u16>,
+    /// Bitset of quarantined cells, 64 cells per word. Quarantined infected
+    /// cells still recover but do not infect their neighbors.
+    pub quarantined: Vec<u64>,
+    /// `AgeBracket` of each cell, as `u8`. Scales recovery via `params.age_gamma`.
+    pub ages: Vec<u8>,
+    /// Day each location was first infected, or `NEVER_INFECTED`. Stays with the
+    /// location rather than moving with travelling cells.
+    pub infection_days: Vec<u32>,
+    /// Steps each infected cell has been infected (0 in the step after infection).
+    /// Only used when `params.infectiousness` is not flat.
+    pub days_infected: Vec<u16>,
+    /// Step on which each infected cell's current infection began. Only used
+    /// while transmissions are tracked; see `track_transmissions`.
+    pub infected_on: Vec<u32>,
+    /// Secondary infections attributed to the cells infected on each step.
+    /// Indexed by step rather than by cell.
+    pub transmissions: Vec<f64>,
+    /// Steps taken with these layers; stamps `infection_days`.
+    pub steps: u32,
+}
+
+/// `CellLayers::infection_days` entry for a location that has never been infected.
+pub const NEVER_INFECTED: u32 = u32::MAX;
+
+impl CellLayers {
+    /// Layers with an age bracket drawn for every cell of `grid`.
+    /// `distribution` holds the relative weight of each `AgeBracket`.
+    pub fn init_age_structured(grid: &Grid, distribution: [f64; 3], rng: &mut impl Rng) -> Self {
+        let total: f64 = distribution.iter().sum();
+        assert!(total > 0.0, "Age distribution must have positive weight");
+        let ages = (0..grid.grid_x * grid.grid_y)
+            .map(|_| {
+                let mut roll = rng.r#gen::<f64>() * total;
+                let mut bracket = AgeBracket::Elderly;
+                for (age, &weight) in distribution.iter().enumerate() {
+                    if roll < weight {
+                        bracket = AgeBracket::from_u8(age as u8);
+                        break;
+                    }
+                    roll -= weight;
+                }
+                bracket as u8
+            })
+            .collect();
+        CellLayers { ages, ..CellLayers::default() }
+    }
+
+    /// Transmission weight of the infected cell at `idx` under `profile`.
+    /// 1 when days of infection are not tracked.
+    pub fn infectiousness(&self, idx: usize, profile: &InfectiousnessProfile) -> f64 {
+        self.days_infected.get(idx).map_or(1.0, |&days| profile.weight(days))
+    }
+
+    /// Advance `days_infected` from the `before` to the `after` grid of a step:
+    /// cells still infected get a day older, newly infected ones start at 0.
+    pub fn advance_days_infected(&mut self, before: &Grid, after: &Grid) {
+        for (idx, days) in self.days_infected.iter_mut().enumerate() {
+            if after.read(idx) == HealthState::Infected {
+                *days = if before.read(idx) == HealthState::Infected { days.saturating_add(1) } else { 0 };
+            }
+        }
+    }
+
+    /// Start tracking `infection_days`; cells infected now are stamped with the current step.
+    pub fn track_infection_days(&mut self, grid: &Grid) {
+        self.infection_days = (0..grid.grid_x * grid.grid_y)
+            .map(|idx| match grid.read(idx) {
+                HealthState::Infected => self.steps,
+                _ => NEVER_INFECTED,
+            })
+            .collect();
+    }
+
+    /// Start attributing new infections to their sources; cells infected now
+    /// count as infected on the current step.
+    pub fn track_transmissions(&mut self, grid: &Grid) {
+        self.infected_on = vec![self.steps; grid.grid_x * grid.grid_y];
+        self.transmissions.clear();
+    }
+
+    /// Credit `share` of a new infection to the infected cell at `source`.
+    /// Does nothing unless transmissions are tracked.
+    pub fn record_transmission(&mut self, source: usize, share: f64) {
+        let Some(&day) = self.infected_on.get(source) else {
+            return;
+        };
+        let day = day as usize;
+        if self.transmissions.len() <= day {
+            self.transmissions.resize(day + 1, 0.0);
+        }
+        self.transmissions[day] += share;
+    }
+
+    /// Record a new infection at `idx` finishing the current step: stamps
+    /// `infection_days` if it is the location's first, and `infected_on`.
+    pub fn record_infection(&mut self, idx: usize) {
+        if let Some(day) = self.infection_days.get_mut(idx)
+            && *day == NEVER_INFECTED
+        {
+            *day = self.steps + 1;
+        }
+        if let Some(day) = self.infected_on.get_mut(idx) {
+            *day = self.steps + 1;
+        }
+    }
+
+    /// Age bracket of the cell at `idx`, or `None` when ages are not tracked.
+    pub fn age_at(&self, idx: usize) -> Option<AgeBracket> {
+        self.ages.get(idx).map(|&age| AgeBracket::from_u8(age))
+    }
+
+    /// Allocate the layers `params` needs for `grid`. Cells that are already
+    /// infected get an infectious period drawn from `rng`.
+    pub fn prepare(&mut self, grid: &Grid, params: &SirParams, rng: &mut impl Rng) {
+        let total_cells = grid.grid_x * grid.grid_y;
+        if params.infectious_period.is_timed() && self.infection_timers.len() != total_cells {
+            self.infection_timers = (0..total_cells)
+                .map(|idx| match grid.read(idx) {
+                    HealthState::Infected => params.infectious_period.draw(rng),
+                    _ => 0,
+                })
+                .collect();
+        }
+        if !params.infectiousness.is_flat() && self.days_infected.len() != total_cells {
+            self.days_infected = vec![0; total_cells];
+        }
+        let words = total_cells.div_ceil(64);
+        if params.quarantine_prob > 0.0 && self.quarantined.len() != words {
+            self.quarantined = vec![0; words];
+        }
+    }
+
+    /// True if the cell at `idx` is quarantined. Always false when the layer is unused.
+    pub fn is_quarantined(&self, idx: usize) -> bool {
+        self.quarantined
+            .get(idx / 64)
+            .is_some_and(|word| word & (1 << (idx % 64)) != 0)
+    }
+
+    /// Exchange the per-cell data of two cells, for layers that are in use.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        if !self.infection_timers.is_empty() {
+            self.infection_timers.swap(a, b);
+        }
+        if !self.ages.is_empty() {
+            self.ages.swap(a, b);
+        }
+        if !self.days_infected.is_empty() {
+            self.days_infected.swap(a, b);
+        }
+        if !self.infected_on.is_empty() {
+            self.infected_on.swap(a, b);
+        }
+        let (quarantined_a, quarantined_b) = (self.is_quarantined(a), self.is_quarantined(b));
+        if quarantined_a != quarantined_b {
+            self.set_quarantined(a, quarantined_b);
+            self.set_quarantined(b, quarantined_a);
+        }
+    }
+
+    /// Mark or clear quarantine for the cell at `idx`, allocating the layer on first use.
+    pub fn set_quarantined(&mut self, idx: usize, quarantined: bool) {
+        if self.quarantined.len() <= idx / 64 {
+            if !quarantined {
+                return;
+            }
+            self.quarantined.resize(idx / 64 + 1, 0);
+        }
+        let bit = 1 << (idx % 64);
+        if quarantined {
+            self.quarantined[idx / 64] |= bit;
+        } else {
+            self.quarantined[idx / 64] &= !bit;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_quarantined_case1() {
+        let mut layers = CellLayers::default();
+        assert!(!layers.is_quarantined(70));
+        layers.set_quarantined(70, true);
+        assert!(layers.is_quarantined(70));
+        assert!(!layers.is_quarantined(69));
+        layers.set_quarantined(70, false);
+        assert!(!layers.is_quarantined(70));
+    }
+
+    #[test]
+    fn test_layers_init_age_structured_case1() {
+        use rand::SeedableRng;
+        let params = SirParams::default();
+        let grid = Grid::init(10, 10, &params);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+
+        let layers = CellLayers::init_age_structured(&grid, [0.0, 1.0, 0.0], &mut rng);
+        assert_eq!(layers.ages.len(), 100);
+        assert!((0..100).all(|idx| layers.age_at(idx) == Some(AgeBracket::Adult)));
+
+        let layers = CellLayers::init_age_structured(&grid, [1.0, 0.0, 1.0], &mut rng);
+        assert!(layers.ages.contains(&(AgeBracket::Child as u8)));
+        assert!(!layers.ages.contains(&(AgeBracket::Adult as u8)));
+        assert_eq!(CellLayers::default().age_at(0), None);
+    }
+}
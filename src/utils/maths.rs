@@ -1,12 +1,370 @@
-﻿pub struct SirParams {
+﻿use serde::{Deserialize, Serialize};
+
+/// Smallest share of `gamma` that `crowding_penalty` leaves an infected cell.
+pub const MIN_CROWDING_FACTOR: f64 = 0.05;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SirParams {
     pub beta: f64,   // Infection rate
     pub gamma: f64,  // Recovery rate
     pub dt:f64,
     pub i_ratio: f64,
+    /// Fraction of cells that start Susceptible. Whatever `i_ratio`, `r_ratio`
+    /// and `s_ratio` leave uncovered also starts Recovered; 1.0 makes every
+    /// remaining cell Susceptible.
     pub s_ratio: f64,
+    /// Fraction of cells that start Recovered (prior immunity).
+    pub r_ratio: f64,
+    /// Optional per-cell infection rate, indexed like the grid cells.
+    /// Overrides `beta` wherever it is present.
+    pub beta_field: Option<Vec<f64>>,
+    /// Which surrounding cells can transmit the infection.
+    pub neighborhood: Neighborhood,
+    /// Rate at which recovered cells lose immunity (SIRS). 0 means permanent immunity.
+    pub waning_rate: f64,
+    /// Weight each infected neighbor by `1 / distance`, so diagonals count less than
+    /// orthogonal neighbors. False gives every neighbor equal weight.
+    pub distance_weighted: bool,
+    /// How many cells out the neighborhood reaches. 1 is the immediate neighbors.
+    pub radius: usize,
+    /// How long infected cells stay infectious. `Geometric` uses `gamma`.
+    pub infectious_period: InfectiousPeriod,
+    /// Rate at which susceptible cells are vaccinated (moved straight to Recovered).
+    pub vaccination_rate: f64,
+    /// Probability that a newly infected cell is quarantined and stops transmitting.
+    pub quarantine_prob: f64,
+    /// Fraction of cells that swap places with a random other cell each day (travel).
+    pub mobility: f64,
+    /// Susceptibility of recovered cells relative to susceptible ones.
+    /// 0 is full immunity, 1 is none.
+    pub reinfection_factor: f64,
+    /// Multiplier on `gamma` for each `AgeBracket`, used when cells carry ages
+    /// (see `CellLayers::init_age_structured`).
+    pub age_gamma: [f64; 3],
+    /// How infectious a cell is over the course of its infection.
+    pub infectiousness: InfectiousnessProfile,
+    /// Per-offset transmission weights. When set, replaces `neighborhood`,
+    /// `radius` and `distance_weighted` in the infection probability.
+    /// Offsets are on the square lattice, so hex row parity is ignored.
+    pub kernel: Option<InfectionKernel>,
+    /// Days before a recovery shows up in reported counts (see
+    /// `History::reported_stats`). Recovery itself happens immediately.
+    pub reporting_delay: usize,
+    /// How a susceptible cell's infected neighbors combine into its infection probability.
+    pub contact_model: ContactModel,
+    /// Extra neighborhood weight of each recovered neighbor, on top of the 1 it
+    /// already counts. Recovered cells then dilute the infected share more than
+    /// susceptible ones, as if standing between a cell and its other contacts.
+    /// 0 leaves recovered neighbors inert.
+    pub recovered_shielding: f64,
+    /// How much local crowding slows recovery: an infected cell recovers at
+    /// `gamma * (1 - crowding_penalty * f)`, where `f` is the fraction of its
+    /// neighbors that are infected, floored at `MIN_CROWDING_FACTOR * gamma` so
+    /// crowded cells still recover. 0 disables the effect.
+    pub crowding_penalty: f64,
+}
+
+impl Default for SirParams {
+    fn default() -> Self {
+        SirParams {
+            beta: 0.3,
+            gamma: 0.1,
+            dt: 1.0,
+            i_ratio: 0.01,
+            s_ratio: 1.0,
+            r_ratio: 0.0,
+            beta_field: None,
+            neighborhood: Neighborhood::Moore,
+            waning_rate: 0.0,
+            distance_weighted: false,
+            radius: 1,
+            infectious_period: InfectiousPeriod::Geometric,
+            vaccination_rate: 0.0,
+            quarantine_prob: 0.0,
+            mobility: 0.0,
+            reinfection_factor: 0.0,
+            age_gamma: [1.0; 3],
+            infectiousness: InfectiousnessProfile::Flat,
+            kernel: None,
+            reporting_delay: 0,
+            contact_model: ContactModel::Linear,
+            recovered_shielding: 0.0,
+            crowding_penalty: 0.0,
+        }
+    }
+}
+
+impl SirParams {
+    /// Fraction of cells `Grid::init` makes Recovered: `r_ratio` plus whatever
+    /// share `1 - i_ratio - r_ratio - s_ratio` is left over, if any.
+    pub fn initial_recovered_ratio(&self) -> f64 {
+        self.r_ratio + (1.0 - self.i_ratio - self.r_ratio - self.s_ratio).max(0.0)
+    }
+
+    /// Check every field against its allowed range: rates (including every
+    /// `beta_field` entry) non-negative, probabilities and initial ratios within
+    /// [0, 1], `dt` and a gamma `infectious_period`'s shape and mean positive,
+    /// and `radius` at least 1. Reports the first offending field.
+    pub fn validate(&self) -> Result<(), SirError> {
+        let check = |name: &'static str, value: f64, ok: bool| {
+            if ok && value.is_finite() { Ok(()) } else { Err(SirError::InvalidParam { name, value }) }
+        };
+        let unit = |value: f64| (0.0..=1.0).contains(&value);
+        check("beta", self.beta, self.beta >= 0.0)?;
+        if let Some(field) = &self.beta_field {
+            for &beta in field {
+                check("beta_field", beta, beta >= 0.0)?;
+            }
+        }
+        check("gamma", self.gamma, self.gamma >= 0.0)?;
+        check("dt", self.dt, self.dt > 0.0)?;
+        check("i_ratio", self.i_ratio, unit(self.i_ratio))?;
+        check("s_ratio", self.s_ratio, unit(self.s_ratio))?;
+        check("r_ratio", self.r_ratio, unit(self.r_ratio))?;
+        check("i_ratio + r_ratio", self.i_ratio + self.r_ratio, self.i_ratio + self.r_ratio <= 1.0)?;
+        check("waning_rate", self.waning_rate, self.waning_rate >= 0.0)?;
+        check("vaccination_rate", self.vaccination_rate, self.vaccination_rate >= 0.0)?;
+        check("quarantine_prob", self.quarantine_prob, unit(self.quarantine_prob))?;
+        check("mobility", self.mobility, unit(self.mobility))?;
+        check("reinfection_factor", self.reinfection_factor, self.reinfection_factor >= 0.0)?;
+        check("recovered_shielding", self.recovered_shielding, self.recovered_shielding >= 0.0)?;
+        check("crowding_penalty", self.crowding_penalty, self.crowding_penalty >= 0.0)?;
+        for &factor in &self.age_gamma {
+            check("age_gamma", factor, factor >= 0.0)?;
+        }
+        if let InfectiousPeriod::Gamma { shape, mean } = self.infectious_period {
+            check("infectious_period shape", shape, shape > 0.0)?;
+            check("infectious_period mean", mean, mean > 0.0)?;
+        }
+        check("radius", self.radius as f64, self.radius >= 1)
+    }
+
+    /// False if these parameters rule out an epidemic on a randomly seeded grid:
+    /// nothing starts infected (`i_ratio` 0) or nothing can transmit (see
+    /// `can_transmit`). Grids seeded by hand, e.g. with `Grid::init_with_seeds`,
+    /// only need `can_transmit`. A usability check, not a validation error.
+    pub fn epidemic_feasible(&self) -> bool {
+        self.i_ratio > 0.0 && self.can_transmit()
+    }
+
+    /// False if no infected cell can ever infect another: `dt` is 0, `beta`
+    /// (or every `beta_field` entry) is 0, or the `kernel` has no weight off-centre.
+    pub fn can_transmit(&self) -> bool {
+        let any_beta = match &self.beta_field {
+            Some(field) => field.iter().any(|&beta| beta > 0.0),
+            None => self.beta > 0.0,
+        };
+        let any_contact = self.kernel.as_ref().is_none_or(|kernel| kernel.offsets().next().is_some());
+        self.dt > 0.0 && any_beta && any_contact
+    }
+
+    /// Infection rate at linear cell index `idx`.
+    pub fn beta_at(&self, idx: usize) -> f64 {
+        match &self.beta_field {
+            Some(field) => field[idx],
+            None => self.beta,
+        }
+    }
+
+    /// Recovery rate for a cell in `age`; plain `gamma` when ages are not tracked.
+    pub fn gamma_for(&self, age: Option<AgeBracket>) -> f64 {
+        match age {
+            Some(age) => self.gamma * self.age_gamma[age as usize],
+            None => self.gamma,
+        }
+    }
+
+    /// Factor `crowding_penalty` applies to the recovery rate of a cell whose
+    /// neighbors are `infected_fraction` infected, never below `MIN_CROWDING_FACTOR`.
+    pub fn crowding_factor(&self, infected_fraction: f64) -> f64 {
+        (1.0 - self.crowding_penalty * infected_fraction).max(MIN_CROWDING_FACTOR)
+    }
+}
+
+/// Relative infectiousness by days since infection, peaking at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum InfectiousnessProfile {
+    /// Equally infectious throughout.
+    #[default]
+    Flat,
+    /// Rises linearly to full strength `peak` days after infection, then falls
+    /// to zero at `duration` days.
+    Triangular { peak: f64, duration: f64 },
+    /// Gamma-density shape (in days) scaled so its peak is 1.
+    Gamma { shape: f64, scale: f64 },
+}
+
+impl InfectiousnessProfile {
+    /// True if every day of infection counts the same.
+    pub fn is_flat(&self) -> bool {
+        matches!(self, InfectiousnessProfile::Flat)
+    }
+
+    /// Weight in [0, 1] of a cell infected `days` steps ago (0 = infected in the last step).
+    pub fn weight(&self, days: u16) -> f64 {
+        let day = days as f64;
+        match *self {
+            InfectiousnessProfile::Flat => 1.0,
+            InfectiousnessProfile::Triangular { peak, duration } => {
+                if day < peak {
+                    (day + 1.0) / (peak + 1.0)
+                } else {
+                    ((duration - day) / (duration - peak)).clamp(0.0, 1.0)
+                }
+            }
+            InfectiousnessProfile::Gamma { shape, scale } => {
+                // Day d is evaluated at d + 1 so the day of infection is not forced to 0
+                let x = day + 1.0;
+                let mode = ((shape - 1.0) * scale).max(1.0);
+                ((x / mode).powf(shape - 1.0) * (-(x - mode) / scale).exp()).min(1.0)
+            }
+        }
+    }
+}
+
+/// How infected neighbors combine into a susceptible cell's infection probability.
+/// Each of the `n` (weighted) neighbors is one contact, `k` of them infected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ContactModel {
+    /// `beta * dt * k / n`, clamped to 1, so a high `beta * dt` reaches certainty
+    /// before every neighbor is infected.
+    #[default]
+    Linear,
+    /// Independent contacts, each transmitting with probability `beta * dt / n`:
+    /// `1 - (1 - beta * dt / n)^k`. Never exceeds 1 and stays below the linear
+    /// value, with more infected neighbors adding less and less.
+    Saturating,
+}
+
+/// Square matrix of transmission weights by neighbor offset, centred on the
+/// cell being infected. The centre entry is ignored (a cell doesn't infect itself).
+/// Deserializing goes through `InfectionKernel::new`, so saved kernels are re-checked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawKernel")]
+pub struct InfectionKernel {
+    size: usize,
+    weights: Vec<f64>,
+}
+
+/// Unchecked `InfectionKernel` fields, as read from a saved run.
+#[derive(Deserialize)]
+struct RawKernel {
+    size: usize,
+    weights: Vec<f64>,
+}
+
+impl TryFrom<RawKernel> for InfectionKernel {
+    type Error = SirError;
+
+    fn try_from(raw: RawKernel) -> Result<Self, SirError> {
+        InfectionKernel::new(raw.size, raw.weights)
+    }
+}
+
+impl InfectionKernel {
+    /// Kernel from `weights` in row-major order, top row first. `size` must be
+    /// odd, `weights` must hold `size * size` entries and each must be a
+    /// finite, non-negative number.
+    pub fn new(size: usize, weights: Vec<f64>) -> Result<Self, SirError> {
+        if size.is_multiple_of(2) {
+            return Err(SirError::InvalidParam { name: "kernel size", value: size as f64 });
+        }
+        if weights.len() != size * size {
+            return Err(GridError::LengthMismatch { expected: size * size, found: weights.len() }.into());
+        }
+        if let Some(&weight) = weights.iter().find(|weight| !(weight.is_finite() && **weight >= 0.0)) {
+            return Err(SirError::InvalidParam { name: "kernel weight", value: weight });
+        }
+        Ok(InfectionKernel { size, weights })
+    }
+
+    /// 3x3 kernel for direction-biased spread: left/right neighbors weigh
+    /// `horizontal`, up/down neighbors `vertical`, and diagonals, which move
+    /// along both axes, their geometric mean. `(1.0, 1.0)` is plain Moore.
+    pub fn anisotropic(horizontal: f64, vertical: f64) -> Result<Self, SirError> {
+        let diagonal = (horizontal * vertical).sqrt();
+        Self::new(3, vec![
+            diagonal, vertical, diagonal,
+            horizontal, 0.0, horizontal,
+            diagonal, vertical, diagonal,
+        ])
+    }
+
+    /// Side length of the matrix.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// (dx, dy, weight) of every non-zero entry except the centre.
+    pub fn offsets(&self) -> impl Iterator<Item = (isize, isize, f64)> + '_ {
+        let half = (self.size / 2) as isize;
+        self.weights.iter().enumerate().filter_map(move |(i, &weight)| {
+            let dx = (i % self.size) as isize - half;
+            let dy = (i / self.size) as isize - half;
+            ((dx, dy) != (0, 0) && weight > 0.0).then_some((dx, dy, weight))
+        })
+    }
+}
+
+/// Age bracket of a cell in an age-structured run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AgeBracket {
+    Child   = 0,
+    Adult   = 1,
+    Elderly = 2,
+}
+
+impl AgeBracket {
+    /// Bracket for a stored `u8`; anything past `Elderly` reads as `Elderly`.
+    pub fn from_u8(value: u8) -> AgeBracket {
+        match value {
+            0 => AgeBracket::Child,
+            1 => AgeBracket::Adult,
+            _ => AgeBracket::Elderly,
+        }
+    }
 }
-/*
-//Future use in an ODE based approach rather than an agent-based approach
+
+/// How long an infected cell stays infectious.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum InfectiousPeriod {
+    /// Recover with probability `gamma * dt` every step (exponentially distributed durations).
+    #[default]
+    Geometric,
+    /// Recover after exactly this many steps.
+    Fixed(u16),
+    /// Duration in steps drawn from a gamma distribution with this shape and mean.
+    Gamma { shape: f64, mean: f64 },
+}
+
+impl InfectiousPeriod {
+    /// True if recovery is driven by per-cell timers rather than `gamma`.
+    pub fn is_timed(&self) -> bool {
+        !matches!(self, InfectiousPeriod::Geometric)
+    }
+
+    /// Draw an infectious duration in steps (at least 1).
+    /// Geometric periods have no predetermined duration and return 0.
+    /// A gamma shape or mean that `SirParams::validate` would reject doesn't
+    /// panic: the period is then `mean` steps, or 1 if `mean` isn't a number.
+    pub fn draw(&self, rng: &mut impl Rng) -> u16 {
+        match *self {
+            InfectiousPeriod::Geometric => 0,
+            InfectiousPeriod::Fixed(steps) => steps.max(1),
+            InfectiousPeriod::Gamma { shape, mean } => {
+                let steps = match Gamma::new(shape, mean / shape) {
+                    Ok(distribution) if mean > 0.0 => distribution.sample(rng),
+                    _ => mean,
+                };
+                if steps.is_nan() { 1 } else { steps.round().clamp(1.0, u16::MAX as f64) as u16 }
+            }
+        }
+    }
+}
+
+/// One forward-Euler step of the well-mixed SIR equations on population fractions.
+/// The mean-field counterpart of the agent-based grid.
 pub fn update_sir(s: f64, i: f64, r: f64, params: &SirParams, dt: f64) -> (f64, f64, f64) {
     let ds = -params.beta * s * i * dt;
     let di = (params.beta * s * i - params.gamma * i) * dt;
@@ -14,18 +372,80 @@ pub fn update_sir(s: f64, i: f64, r: f64, params: &SirParams, dt: f64) -> (f64,
 
     (s + ds, i + di, r + dr)
 }
-*/
-use crate::utils::grid::{Grid, HealthState};
+
+/// Mean-field SIR state as population fractions, advanced with `update_sir`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OdeSir {
+    pub s: f64,
+    pub i: f64,
+    pub r: f64,
+}
+
+impl OdeSir {
+    /// Start with an infected fraction `i` and everyone else susceptible.
+    pub fn new(i: f64) -> Self {
+        OdeSir { s: 1.0 - i, i, r: 0.0 }
+    }
+
+    /// Advance the fractions by `dt`.
+    pub fn step(&mut self, params: &SirParams, dt: f64) {
+        (self.s, self.i, self.r) = update_sir(self.s, self.i, self.r, params, dt);
+    }
+
+    /// Take `days` steps of `params.dt`, returning the state before the first
+    /// step and after each one (`days + 1` entries), like `History`.
+    pub fn run(&mut self, params: &SirParams, days: usize) -> Vec<OdeSir> {
+        let mut trajectory = Vec::with_capacity(days + 1);
+        trajectory.push(*self);
+        for _ in 0..days {
+            self.step(params, params.dt);
+            trajectory.push(*self);
+        }
+        trajectory
+    }
+}
+use crate::utils::error::SirError;
+use crate::utils::grid::{Grid, GridError, HealthState, Neighborhood};
+use rand::Rng;
+use rand_distr::{Distribution, Gamma};
 
 /// Holds counts of how many people are in each state.
 /// This is used to track how the disease progresses over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PopulationStats {
     pub susceptible: usize,
     pub infected: usize,
     pub recovered: usize,
 }
 
+impl PopulationStats {
+    /// Inhabited cells: S + I + R.
+    pub fn total(&self) -> usize {
+        self.susceptible + self.infected + self.recovered
+    }
+
+    /// (S, I, R) as fractions of `total`; all zero for an empty population.
+    pub fn fractions(&self) -> (f64, f64, f64) {
+        let total = self.total();
+        if total == 0 {
+            return (0.0, 0.0, 0.0);
+        }
+        let total = total as f64;
+        (self.susceptible as f64 / total, self.infected as f64 / total, self.recovered as f64 / total)
+    }
+
+    /// True if each of the S, I and R fractions is within `tol` of `other`'s,
+    /// relative to the larger of the two (two zero fractions always match).
+    pub fn approx_eq(&self, other: &PopulationStats, tol: f64) -> bool {
+        let (a, b) = (self.fractions(), other.fractions());
+        [(a.0, b.0), (a.1, b.1), (a.2, b.2)]
+            .into_iter()
+            .all(|(x, y)| (x - y).abs() <= tol * x.max(y))
+    }
+}
+
 /// Count how many cells are in each HealthState (S, I, or R).
+/// Empty cells are not part of the population and are skipped.
 /// This is useful for statistics and visualizing or logging simulation progress.
 pub fn count_states(grid: &Grid) -> PopulationStats {
     // Initialize all counts to zero
@@ -35,19 +455,436 @@ pub fn count_states(grid: &Grid) -> PopulationStats {
         recovered: 0,
     };
 
-    // Iterate over every cell by linear index
-    let total_cells = grid.grid_x * grid.grid_y;
-    for idx in 0..total_cells {
+    // Iterate over every cell by linear index; coordinates aren't needed
+    for idx in 0..grid.grid_x * grid.grid_y {
         match grid.read(idx) {
             HealthState::Susceptible => stats.susceptible += 1,
             HealthState::Infected    => stats.infected    += 1,
             HealthState::Recovered   => stats.recovered   += 1,
+            HealthState::Empty       => {}
         }
     }
 
     stats
 }
 
+/// Why a run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationReason {
+    /// No infected cells remain.
+    Extinct,
+    /// The day limit was reached with the epidemic still active.
+    MaxDays,
+    /// A step left the grid unchanged, so no later step can change it either.
+    SteadyState,
+}
+
+/// True once the epidemic can no longer progress (no infected cells left).
+pub fn is_finished(stats: &PopulationStats) -> bool {
+    stats.infected == 0
+}
+
+/// Whether a run should stop after `day` steps out of `max_days`, and why.
+pub fn termination_reason(stats: &PopulationStats, day: usize, max_days: usize) -> Option<TerminationReason> {
+    if is_finished(stats) {
+        Some(TerminationReason::Extinct)
+    } else if day >= max_days {
+        Some(TerminationReason::MaxDays)
+    } else {
+        None
+    }
+}
+
+/// For every possible byte, how many of its four 2-bit cells hold each state
+/// (indexed by `HealthState as usize`).
+const BYTE_STATE_COUNTS: [[u8; 4]; 256] = build_byte_state_counts();
+
+const fn build_byte_state_counts() -> [[u8; 4]; 256] {
+    let mut table = [[0u8; 4]; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut slot = 0;
+        while slot < 4 {
+            let state = (byte >> (slot * 2)) & 0b11;
+            table[byte][state] += 1;
+            slot += 1;
+        }
+        byte += 1;
+    }
+    table
+}
+
+/// Same result as `count_states`, but counts whole packed bytes at a time
+/// through a 256-entry lookup table. Only the partial final byte is read
+/// cell by cell, so its padding bits are never counted.
+pub fn count_states_fast(grid: &Grid) -> PopulationStats {
+    let total_cells = grid.grid_x * grid.grid_y;
+    let full_bytes = total_cells / 4;
+
+    let mut totals = [0usize; 4];
+    for &byte in &grid.cells[..full_bytes] {
+        let counts = &BYTE_STATE_COUNTS[byte as usize];
+        for (total, &count) in totals.iter_mut().zip(counts) {
+            *total += count as usize;
+        }
+    }
+    for idx in full_bytes * 4..total_cells {
+        totals[grid.read(idx) as usize] += 1;
+    }
+
+    PopulationStats {
+        susceptible: totals[HealthState::Susceptible as usize],
+        infected: totals[HealthState::Infected as usize],
+        recovered: totals[HealthState::Recovered as usize],
+    }
+}
+
+/// Day-by-day record of population counts.
+/// Entry 0 is the initial state, entry `n` the state after `n` steps.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub days: Vec<PopulationStats>,
+    /// Every infection so far, including the initial seeds. Unlike
+    /// `recovered + infected` this stays correct when cells are reinfected.
+    pub cumulative_infections: usize,
+    /// Infections per simulated day: the initially infected, then each step's
+    /// new infections. Unlike `days` this also covers `Simulation::advance`.
+    pub incidence: Vec<usize>,
+    /// Secondary infections caused by the cells infected on each day, indexed
+    /// like `incidence`. Only kept with `Simulation::with_transmission_tracking`.
+    pub transmissions: Vec<f64>,
+    /// Transitions made on the step leading to each entry of `days`; entry 0,
+    /// the initial state, has none. Filled by `Simulation::step`.
+    pub transitions: Vec<TransitionCounts>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    /// Start a history from the initial counts; the initially infected are the first infections.
+    pub fn from_initial(stats: PopulationStats) -> Self {
+        History {
+            days: vec![stats],
+            cumulative_infections: stats.infected,
+            incidence: vec![stats.infected],
+            transmissions: Vec::new(),
+            transitions: vec![TransitionCounts::default()],
+        }
+    }
+
+    /// Append the counts for the next day.
+    pub fn push(&mut self, stats: PopulationStats) {
+        self.days.push(stats);
+    }
+
+    /// Append the counts after a step that produced `new_infections`.
+    pub fn record_step(&mut self, stats: PopulationStats, new_infections: usize) {
+        self.days.push(stats);
+        self.cumulative_infections += new_infections;
+        self.incidence.push(new_infections);
+    }
+
+    pub fn len(&self) -> usize {
+        self.days.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.days.is_empty()
+    }
+
+    /// Counts for `day` as surveillance would report them when recoveries are
+    /// reported `delay` days late: the recovered count is the one from `delay`
+    /// days earlier (day 0's before then), and cells whose recovery isn't
+    /// reported yet are still counted as infected. `None` if `day` isn't recorded.
+    pub fn reported_stats(&self, day: usize, delay: usize) -> Option<PopulationStats> {
+        let actual = *self.days.get(day)?;
+        let lagged = self.days[day.saturating_sub(delay)].recovered;
+        let infected_or_recovered = actual.infected + actual.recovered;
+        let recovered = lagged.min(infected_or_recovered);
+        Some(PopulationStats { susceptible: actual.susceptible, infected: infected_or_recovered - recovered, recovered })
+    }
+
+    /// First day on which the recovered fraction of the population reached `threshold`.
+    pub fn herd_immunity_day(&self, threshold: f64) -> Option<usize> {
+        self.days.iter().position(|stats| {
+            let total = stats.total();
+            total > 0 && stats.recovered as f64 / total as f64 >= threshold
+        })
+    }
+
+    /// Days from the first day the infected count exceeded `threshold` to the
+    /// first later day it was back at or below it (which includes extinction).
+    /// An outbreak that never exceeds `threshold` lasts 0 days; one still above
+    /// it on the last recorded day is measured to that day and marked censored.
+    pub fn epidemic_duration(&self, threshold: usize) -> EpidemicDuration {
+        let Some(start) = self.days.iter().position(|stats| stats.infected > threshold) else {
+            return EpidemicDuration { days: 0, censored: false };
+        };
+        match self.days[start..].iter().position(|stats| stats.infected <= threshold) {
+            Some(offset) => EpidemicDuration { days: offset, censored: false },
+            None => EpidemicDuration { days: self.days.len() - 1 - start, censored: true },
+        }
+    }
+}
+
+/// Number of cells that made each transition during one step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TransitionCounts {
+    /// Infection.
+    pub s_to_i: usize,
+    /// Recovery.
+    pub i_to_r: usize,
+    /// Vaccination.
+    pub s_to_r: usize,
+    /// Loss of immunity.
+    pub r_to_s: usize,
+    /// Reinfection.
+    pub r_to_i: usize,
+}
+
+impl TransitionCounts {
+    /// Count one cell going from `from` to `to`. Cells that keep their state
+    /// count nothing.
+    pub fn record(&mut self, from: HealthState, to: HealthState) {
+        use HealthState::*;
+        match (from, to) {
+            (Susceptible, Infected) => self.s_to_i += 1,
+            (Infected, Recovered) => self.i_to_r += 1,
+            (Susceptible, Recovered) => self.s_to_r += 1,
+            (Recovered, Susceptible) => self.r_to_s += 1,
+            (Recovered, Infected) => self.r_to_i += 1,
+            _ => {}
+        }
+    }
+
+    /// New infections, including reinfections.
+    pub fn new_infections(&self) -> usize {
+        self.s_to_i + self.r_to_i
+    }
+}
+
+/// Length of an outbreak, see `History::epidemic_duration`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpidemicDuration {
+    pub days: usize,
+    /// The run ended before infections fell back below the threshold, so
+    /// `days` is a lower bound.
+    pub censored: bool,
+}
+
+/// Share of cells ever infected (now Infected or Recovered) in each ring of
+/// Chebyshev radius 0..=`max_r` around `seed`. Empty and out-of-bounds cells
+/// are not counted; a ring with no inhabited cells reports 0.
+pub fn attack_rate_by_radius(grid: &Grid, seed: (usize, usize), max_r: usize) -> Vec<f64> {
+    let mut hit = vec![0usize; max_r + 1];
+    let mut total = vec![0usize; max_r + 1];
+    for ((x, y), state) in grid.iter_cells() {
+        let ring = x.abs_diff(seed.0).max(y.abs_diff(seed.1));
+        if ring > max_r || state == HealthState::Empty {
+            continue;
+        }
+        total[ring] += 1;
+        if matches!(state, HealthState::Infected | HealthState::Recovered) {
+            hit[ring] += 1;
+        }
+    }
+    hit.iter()
+        .zip(&total)
+        .map(|(&hit, &total)| if total == 0 { 0.0 } else { hit as f64 / total as f64 })
+        .collect()
+}
+
+/// Label the connected regions of cells matching `include`, linked through
+/// `neighborhood`. Returns a per-cell component id (0 for excluded cells,
+/// 1..=count otherwise) and the number of components.
+fn label_components(grid: &Grid, neighborhood: Neighborhood, include: impl Fn(HealthState) -> bool) -> (Vec<u32>, usize) {
+    let mut labels = vec![0u32; grid.grid_x * grid.grid_y];
+    let mut count = 0;
+    let mut stack = Vec::new();
+    for start in 0..labels.len() {
+        if labels[start] != 0 || !include(grid.read(start)) {
+            continue;
+        }
+        count += 1;
+        labels[start] = count as u32;
+        stack.push(start);
+        while let Some(idx) = stack.pop() {
+            let (x, y) = (idx % grid.grid_x, idx / grid.grid_x);
+            for &(dx, dy) in neighborhood.offsets_at(y) {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if nx < 0 || ny < 0 || nx >= grid.grid_x as isize || ny >= grid.grid_y as isize {
+                    continue;
+                }
+                let n_idx = grid.get_index(nx as usize, ny as usize);
+                if labels[n_idx] == 0 && include(grid.read(n_idx)) {
+                    labels[n_idx] = count as u32;
+                    stack.push(n_idx);
+                }
+            }
+        }
+    }
+    (labels, count)
+}
+
+/// Label the connected clusters of infected cells, linked through `neighborhood`.
+/// Returns a per-cell cluster id (0 for cells that are not infected, 1..=count
+/// otherwise), indexed like the grid cells, and the number of clusters.
+pub fn label_infected_components(grid: &Grid, neighborhood: Neighborhood) -> (Vec<u32>, usize) {
+    label_components(grid, neighborhood, |state| state == HealthState::Infected)
+}
+
+/// Cell count of each infected cluster (Moore connectivity), largest first.
+pub fn infected_cluster_sizes(grid: &Grid) -> Vec<usize> {
+    let (labels, count) = label_infected_components(grid, Neighborhood::Moore);
+    let mut sizes = vec![0; count];
+    for label in labels.into_iter().filter(|&label| label != 0) {
+        sizes[label as usize - 1] += 1;
+    }
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes
+}
+
+/// Moran's I of the infected indicator (1 infected, 0 otherwise) over the
+/// inhabited cells, with each pair of inhabited Moore neighbors weighted 1.
+/// Near 1 means infected cells sit together (a wavefront), near 0 that they
+/// are scattered at random, negative that they avoid each other. 0 when it is
+/// undefined: every inhabited cell in the same state, or no neighboring pairs.
+pub fn morans_i(grid: &Grid) -> f64 {
+    let size = grid.grid_x * grid.grid_y;
+    let inhabited = (0..size).filter(|&idx| grid.read(idx) != HealthState::Empty).count();
+    if inhabited == 0 {
+        return 0.0;
+    }
+    let infected = (0..size).filter(|&idx| grid.read(idx) == HealthState::Infected).count();
+    let mean = infected as f64 / inhabited as f64;
+    let deviation = |state: HealthState| if state == HealthState::Infected { 1.0 - mean } else { -mean };
+
+    let mut variance = 0.0;
+    let mut covariance = 0.0;
+    let mut weight = 0.0;
+    for ((x, y), state) in grid.iter_cells() {
+        if state == HealthState::Empty {
+            continue;
+        }
+        let own = deviation(state);
+        variance += own * own;
+        for (nx, ny) in grid.neighbors(x, y) {
+            let neighbor = grid.read(grid.get_index(nx, ny));
+            if neighbor != HealthState::Empty {
+                covariance += own * deviation(neighbor);
+                weight += 1.0;
+            }
+        }
+    }
+    if variance == 0.0 || weight == 0.0 {
+        return 0.0;
+    }
+    (inhabited as f64 / weight) * covariance / variance
+}
+
+/// First day on which two of the outbreaks infected on day 0 have joined up.
+/// Outbreaks are the connected infected regions of `grids[0]`; each one's
+/// front is followed through the ever-infected (Infected or Recovered) cells,
+/// so a merge is reported once some connected ever-infected region contains
+/// cells of two initial outbreaks. `None` if fewer than two outbreaks were
+/// seeded, or they never meet.
+pub fn fronts_merged_day(grids: &[Grid], neighborhood: Neighborhood) -> Option<usize> {
+    let first = grids.first()?;
+    let (seeds, outbreaks) = label_infected_components(first, neighborhood);
+    if outbreaks < 2 {
+        return None;
+    }
+    grids.iter().position(|grid| {
+        let (regions, count) = label_components(grid, neighborhood, |state| {
+            matches!(state, HealthState::Infected | HealthState::Recovered)
+        });
+        let mut outbreak_of_region = vec![0u32; count + 1];
+        seeds.iter().zip(&regions).any(|(&seed, &region)| {
+            if seed == 0 || region == 0 {
+                return false;
+            }
+            let owner = &mut outbreak_of_region[region as usize];
+            if *owner == 0 {
+                *owner = seed;
+            }
+            *owner != seed
+        })
+    })
+}
+
+/// Time-varying reproduction number: for each day, the mean number of secondary
+/// infections caused by the cells infected that day (`transmissions / incidence`).
+/// Days on which nobody was infected have no cohort and give NaN. The latest
+/// days are biased low, since their cohorts may still be infectious.
+pub fn effective_r(history: &History) -> Vec<f64> {
+    history
+        .incidence
+        .iter()
+        .enumerate()
+        .map(|(day, &cohort)| {
+            let transmissions = history.transmissions.get(day).copied().unwrap_or(0.0);
+            if cohort == 0 { f64::NAN } else { transmissions / cohort as f64 }
+        })
+        .collect()
+}
+
+/// Immune fraction above which each infection causes fewer than one more: `1 - 1/R0`.
+/// Clamped to [0, 1]; with R0 <= 1 no immunity is needed.
+pub fn herd_immunity_threshold(r0: f64) -> f64 {
+    if r0 <= 1.0 {
+        0.0
+    } else {
+        (1.0 - 1.0 / r0).clamp(0.0, 1.0)
+    }
+}
+
+/// Fraction of the initial susceptibles that may be lost before the early
+/// exponential phase is considered over.
+const SATURATION_DROP: f64 = 0.05;
+
+/// Fit the early exponential growth rate of the infected count.
+/// Does a log-linear least-squares fit of `ln(infected)` against time, skipping
+/// day 0 and stopping once the susceptible count has dropped by more than
+/// `SATURATION_DROP` of its initial value. Returns NaN if fewer than two usable
+/// points are available.
+pub fn early_growth_rate(history: &History, dt: f64) -> f64 {
+    let Some(first) = history.days.first() else {
+        return f64::NAN;
+    };
+    let s0 = first.susceptible as f64;
+
+    // Collect (time, ln I) pairs inside the fitting window
+    let mut points = Vec::new();
+    for (day, stats) in history.days.iter().enumerate().skip(1) {
+        if (stats.susceptible as f64) < s0 * (1.0 - SATURATION_DROP) {
+            break;
+        }
+        if stats.infected > 0 {
+            points.push((day as f64 * dt, (stats.infected as f64).ln()));
+        }
+    }
+    if points.len() < 2 {
+        return f64::NAN;
+    }
+
+    // Ordinary least squares slope
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|&(t, _)| t).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let cov: f64 = points.iter().map(|&(t, y)| (t - mean_t) * (y - mean_y)).sum();
+    let var: f64 = points.iter().map(|&(t, _)| (t - mean_t).powi(2)).sum();
+    cov / var
+}
+
+/// Estimate the basic reproduction number from the early growth of an outbreak.
+/// In the SIR model infections initially grow as `exp((beta - gamma) * t)`,
+/// so `R0 = beta / gamma = 1 + r / gamma` for the fitted growth rate `r`.
+pub fn estimate_r0(history: &History, gamma: f64, dt: f64) -> f64 {
+    1.0 + early_growth_rate(history, dt) / gamma
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -56,7 +893,7 @@ mod tests {
     use crate::utils::grid::HealthState;
 
     fn dummy_params(i_ratio: f64) -> SirParams {
-        SirParams { beta: 0.0, gamma: 0.0, dt: 1.0, i_ratio, s_ratio: 1.0 }
+        SirParams { beta: 0.0, gamma: 0.0, dt: 1.0, i_ratio, s_ratio: 1.0, ..SirParams::default() }
     }
 
     #[test]
@@ -74,4 +911,438 @@ mod tests {
         assert_eq!(stats.infected,    2);
         assert_eq!(stats.recovered,   1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    // Empty cells are not counted in any compartment
+    fn test_maths_count_states_case2() {
+        let mut grid = Grid::init(3, 3, &dummy_params(1.0));
+        grid.write(grid.get_index(1, 1), HealthState::Empty);
+
+        let stats = count_states(&grid);
+        assert_eq!(stats.infected, 8);
+        assert_eq!(stats.susceptible + stats.infected + stats.recovered, 8);
+    }
+
+    #[test]
+    // Lookup-table counting agrees with the per-cell scan
+    fn test_maths_count_states_fast_case1() {
+        let mut grid = Grid::init(1000, 1000, &dummy_params(0.3));
+        for idx in (0..1_000_000).step_by(7) {
+            grid.write(idx, HealthState::Recovered);
+        }
+        for idx in (0..1_000_000).step_by(13) {
+            grid.write(idx, HealthState::Empty);
+        }
+        assert_eq!(count_states_fast(&grid), count_states(&grid));
+    }
+
+    #[test]
+    // 7x7 = 49 cells leaves a partial final byte
+    fn test_maths_count_states_fast_case2() {
+        let mut grid = Grid::init(7, 7, &dummy_params(0.5));
+        grid.write(48, HealthState::Recovered);
+        assert_eq!(count_states_fast(&grid), count_states(&grid));
+    }
+
+    #[test]
+    fn test_maths_is_finished_case1() {
+        let active = PopulationStats { susceptible: 5, infected: 1, recovered: 3 };
+        let extinct = PopulationStats { susceptible: 5, infected: 0, recovered: 4 };
+        assert!(!is_finished(&active));
+        assert!(is_finished(&extinct));
+    }
+
+    #[test]
+    fn test_maths_termination_reason_case1() {
+        let active = PopulationStats { susceptible: 5, infected: 1, recovered: 3 };
+        let extinct = PopulationStats { susceptible: 5, infected: 0, recovered: 4 };
+        assert_eq!(termination_reason(&active, 3, 10), None);
+        assert_eq!(termination_reason(&active, 10, 10), Some(TerminationReason::MaxDays));
+        // Extinction takes precedence on the final day
+        assert_eq!(termination_reason(&extinct, 10, 10), Some(TerminationReason::Extinct));
+    }
+
+    #[test]
+    fn test_maths_infectious_period_draw_case1() {
+        let mut rng = rand::thread_rng();
+        assert_eq!(InfectiousPeriod::Geometric.draw(&mut rng), 0);
+        assert_eq!(InfectiousPeriod::Fixed(5).draw(&mut rng), 5);
+        assert_eq!(InfectiousPeriod::Fixed(0).draw(&mut rng), 1);
+        for _ in 0..100 {
+            assert!(InfectiousPeriod::Gamma { shape: 2.0, mean: 6.0 }.draw(&mut rng) >= 1);
+        }
+        // Parameters validate() rejects fall back instead of panicking
+        assert_eq!(InfectiousPeriod::Gamma { shape: 0.0, mean: 7.0 }.draw(&mut rng), 7);
+        assert_eq!(InfectiousPeriod::Gamma { shape: 2.0, mean: -3.0 }.draw(&mut rng), 1);
+        assert_eq!(InfectiousPeriod::Gamma { shape: 2.0, mean: f64::NAN }.draw(&mut rng), 1);
+    }
+
+    #[test]
+    // Gamma-distributed durations average out to the configured mean
+    fn test_maths_infectious_period_draw_case2() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let period = InfectiousPeriod::Gamma { shape: 4.0, mean: 10.0 };
+        let draws: Vec<u16> = (0..10_000).map(|_| period.draw(&mut rng)).collect();
+        let mean = draws.iter().map(|&d| d as f64).sum::<f64>() / draws.len() as f64;
+        assert!(draws.iter().all(|&d| d >= 1));
+        assert!((mean - 10.0).abs() < 0.3, "mean = {}", mean);
+    }
+
+    #[test]
+    fn test_maths_herd_immunity_threshold_case1() {
+        assert!((herd_immunity_threshold(2.0) - 0.5).abs() < 1e-12);
+        assert!((herd_immunity_threshold(4.0) - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_maths_herd_immunity_threshold_case2() {
+        assert_eq!(herd_immunity_threshold(1.0), 0.0);
+        assert_eq!(herd_immunity_threshold(0.5), 0.0);
+    }
+
+    #[test]
+    fn test_maths_herd_immunity_day_case1() {
+        let mut history = History::new();
+        for recovered in [0, 20, 45, 50, 70] {
+            history.push(PopulationStats { susceptible: 90 - recovered, infected: 10, recovered });
+        }
+        assert_eq!(history.herd_immunity_day(0.5), Some(3));
+        assert_eq!(history.herd_immunity_day(0.9), None);
+    }
+
+    // Synthetic SIR-like history with infections growing as I0 * exp(r * t)
+    fn exponential_history(population: usize, i0: f64, rate: f64, days: usize) -> History {
+        let mut history = History::new();
+        for day in 0..=days {
+            let infected = (i0 * (rate * day as f64).exp()).round() as usize;
+            history.push(PopulationStats {
+                susceptible: population - infected,
+                infected,
+                recovered: 0,
+            });
+        }
+        history
+    }
+
+    #[test]
+    fn test_maths_early_growth_rate_case1() {
+        let history = exponential_history(1_000_000, 10.0, 0.2, 30);
+        let rate = early_growth_rate(&history, 1.0);
+        assert!((rate - 0.2).abs() < 1e-2, "rate = {}", rate);
+    }
+
+    #[test]
+    // Once susceptibles are depleted the fit window must stop
+    fn test_maths_early_growth_rate_case2() {
+        let mut history = exponential_history(1_000_000, 10.0, 0.2, 30);
+        // Saturated tail that would drag the slope down if it were included
+        for _ in 0..30 {
+            history.push(PopulationStats { susceptible: 100_000, infected: 10, recovered: 899_990 });
+        }
+        let rate = early_growth_rate(&history, 1.0);
+        assert!((rate - 0.2).abs() < 1e-2, "rate = {}", rate);
+    }
+
+    #[test]
+    fn test_maths_estimate_r0_case1() {
+        // r = beta - gamma = 0.2 with gamma = 0.1 gives R0 = 3
+        let history = exponential_history(1_000_000, 10.0, 0.2, 30);
+        let r0 = estimate_r0(&history, 0.1, 1.0);
+        assert!((r0 - 3.0).abs() < 0.1, "r0 = {}", r0);
+    }
+
+    #[test]
+    fn test_maths_estimate_r0_case2() {
+        let history = History::new();
+        assert!(estimate_r0(&history, 0.1, 1.0).is_nan());
+    }
+
+    #[test]
+    fn test_maths_ode_sir_run_case1() {
+        let params = SirParams { beta: 0.5, gamma: 0.1, dt: 0.5, ..SirParams::default() };
+        let trajectory = OdeSir::new(0.01).run(&params, 200);
+        assert_eq!(trajectory.len(), 201);
+
+        let peak = trajectory
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.i.total_cmp(&b.1.i))
+            .map(|(day, _)| day)
+            .unwrap();
+        assert!(peak > 0 && peak < 200);
+        assert!(trajectory[peak].i > 0.2);
+        assert!(trajectory[200].i < trajectory[peak].i / 10.0);
+        for point in &trajectory {
+            assert!((point.s + point.i + point.r - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_maths_attack_rate_by_radius_case1() {
+        let mut grid = Grid::init(15, 15, &dummy_params(0.0));
+        for y in 4..=10 {
+            for x in 4..=10 {
+                grid.write(grid.get_index(x, y), HealthState::Recovered);
+            }
+        }
+        grid.write(grid.get_index(7, 7), HealthState::Infected);
+        // Half of ring 2 was never reached
+        for x in 5..=9 {
+            grid.write(grid.get_index(x, 5), HealthState::Susceptible);
+        }
+
+        let rates = attack_rate_by_radius(&grid, (7, 7), 9);
+        assert_eq!(rates.len(), 10);
+        assert_eq!(rates[0], 1.0);
+        assert_eq!(rates[1], 1.0);
+        assert!((rates[2] - 11.0 / 16.0).abs() < 1e-12);
+        assert_eq!(rates[3], 1.0);
+        assert!(rates[4..].iter().all(|&rate| rate == 0.0));
+    }
+
+    #[test]
+    fn test_maths_infectiousness_profile_weight_case1() {
+        assert_eq!(InfectiousnessProfile::Flat.weight(0), 1.0);
+        assert_eq!(InfectiousnessProfile::Flat.weight(40), 1.0);
+
+        let triangular = InfectiousnessProfile::Triangular { peak: 3.0, duration: 8.0 };
+        assert_eq!(triangular.weight(0), 0.25);
+        assert_eq!(triangular.weight(3), 1.0);
+        assert!((triangular.weight(6) - 0.4).abs() < 1e-12);
+        assert_eq!(triangular.weight(9), 0.0);
+
+        let gamma = InfectiousnessProfile::Gamma { shape: 3.0, scale: 1.5 };
+        let weights: Vec<f64> = (0..12).map(|day| gamma.weight(day)).collect();
+        assert!((weights[2] - 1.0).abs() < 1e-12);
+        assert!(weights[0] < weights[1] && weights[1] < weights[2]);
+        assert!(weights[2..].windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(weights.iter().all(|&w| (0.0..=1.0).contains(&w)));
+    }
+
+    #[test]
+    fn test_maths_epidemic_duration_case1() {
+        let mut history = History::new();
+        for infected in [2, 4, 12, 30, 25, 11, 6, 0] {
+            history.push(PopulationStats { susceptible: 100 - infected, infected, recovered: 0 });
+        }
+        assert_eq!(history.epidemic_duration(10), EpidemicDuration { days: 4, censored: false });
+        assert_eq!(history.epidemic_duration(0), EpidemicDuration { days: 7, censored: false });
+
+        history.days.truncate(5);
+        assert_eq!(history.epidemic_duration(10), EpidemicDuration { days: 2, censored: true });
+    }
+
+    #[test]
+    fn test_maths_epidemic_duration_case2() {
+        let mut history = History::new();
+        for infected in [3, 5, 2, 1, 0] {
+            history.push(PopulationStats { susceptible: 100 - infected, infected, recovered: 0 });
+        }
+        assert_eq!(history.epidemic_duration(10), EpidemicDuration { days: 0, censored: false });
+        assert_eq!(History::new().epidemic_duration(10), EpidemicDuration { days: 0, censored: false });
+    }
+
+    #[test]
+    fn test_maths_count_states_padding_case1() {
+        // 50 cells in 13 bytes: the top 4 bits of byte 12 are padding
+        let mut grid = Grid::init(10, 5, &dummy_params(0.0));
+        grid.write(49, HealthState::Recovered);
+        let expected = PopulationStats { susceptible: 49, infected: 0, recovered: 1 };
+        // Garbage padding reads as two Infected cells if the counts run past cell 49
+        grid.cells[12] |= 0b0101_0000;
+
+        assert_eq!(count_states(&grid), expected);
+        assert_eq!(count_states_fast(&grid), expected);
+        assert_eq!(grid.count_state(HealthState::Infected), 0);
+        assert!(!grid.any_infected());
+    }
+
+    #[test]
+    fn test_maths_fronts_merged_day_case1() {
+        let params = SirParams { beta: 8.0, gamma: 0.2, ..dummy_params(0.0) };
+        let mut grid = Grid::init_with_seeds(30, 9, &params, &[(3, 4), (26, 4)]);
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let mut grids = vec![grid.clone()];
+        for _ in 0..30 {
+            crate::utils::simulation::step_grid_with_rng(&mut grid, &params, &mut rng);
+            grids.push(grid.clone());
+        }
+
+        let day = fronts_merged_day(&grids, Neighborhood::Moore).expect("fronts should meet");
+        assert!(day > 5 && day <= 30, "day {}", day);
+        assert_eq!(fronts_merged_day(&grids[..day], Neighborhood::Moore), None);
+    }
+
+    #[test]
+    fn test_maths_fronts_merged_day_case2() {
+        let params = dummy_params(0.0);
+        let single = Grid::init_with_seeds(10, 10, &params, &[(5, 5)]);
+        assert_eq!(fronts_merged_day(&[single], Neighborhood::Moore), None);
+
+        // Diagonal seeds are one outbreak under Moore but two under von Neumann,
+        // joined from the start by a recovered cell beside both
+        let mut diagonal = Grid::init_with_seeds(10, 10, &params, &[(5, 5), (6, 6)]);
+        assert_eq!(fronts_merged_day(&[diagonal.clone()], Neighborhood::VonNeumann), None);
+        diagonal.write(diagonal.get_index(6, 5), HealthState::Recovered);
+        assert_eq!(fronts_merged_day(&[diagonal.clone()], Neighborhood::Moore), None);
+        assert_eq!(fronts_merged_day(&[diagonal], Neighborhood::VonNeumann), Some(0));
+    }
+
+    #[test]
+    fn test_maths_label_infected_components_case1() {
+        let mut grid = Grid::init(8, 6, &dummy_params(0.0));
+        // A 2x3 blob in the top left and an L of 4 in the bottom right
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1), (0, 2), (1, 2), (6, 3), (6, 4), (6, 5), (7, 5)] {
+            grid.write(grid.get_index(x, y), HealthState::Infected);
+        }
+        grid.write(grid.get_index(3, 3), HealthState::Recovered);
+
+        let (labels, count) = label_infected_components(&grid, Neighborhood::Moore);
+        assert_eq!(count, 2);
+        assert_eq!(labels.len(), 48);
+        let size = |id: u32| labels.iter().filter(|&&label| label == id).count();
+        assert_eq!(size(1), 6);
+        assert_eq!(size(2), 4);
+        assert_eq!(labels[grid.get_index(1, 2)], 1);
+        assert_eq!(labels[grid.get_index(7, 5)], 2);
+        assert_eq!(labels[grid.get_index(3, 3)], 0);
+        assert_eq!(size(0), 38);
+    }
+
+    #[test]
+    fn test_maths_label_infected_components_case2() {
+        // Diagonal neighbors join under Moore but not under von Neumann
+        let mut grid = Grid::init(4, 4, &dummy_params(0.0));
+        for (x, y) in [(0, 0), (1, 1), (2, 2)] {
+            grid.write(grid.get_index(x, y), HealthState::Infected);
+        }
+        assert_eq!(label_infected_components(&grid, Neighborhood::Moore).1, 1);
+        assert_eq!(label_infected_components(&grid, Neighborhood::VonNeumann).1, 3);
+    }
+
+    #[test]
+    fn test_maths_infected_cluster_sizes_case1() {
+        let mut grid = Grid::init(6, 6, &dummy_params(0.0));
+        assert!(infected_cluster_sizes(&grid).is_empty());
+
+        // A lone cell found first, then a 2x2 block
+        for (x, y) in [(0, 0), (3, 3), (4, 3), (3, 4), (4, 4)] {
+            grid.write(grid.get_index(x, y), HealthState::Infected);
+        }
+        assert_eq!(infected_cluster_sizes(&grid), vec![4, 1]);
+    }
+
+    #[test]
+    fn test_maths_population_stats_fractions_case1() {
+        let mut grid = Grid::init(2, 2, &dummy_params(0.0));
+        grid.write(0, HealthState::Infected);
+        grid.write(3, HealthState::Infected);
+        let stats = count_states(&grid);
+        assert_eq!(stats.total(), 4);
+        assert_eq!(stats.fractions(), (0.5, 0.5, 0.0));
+
+        grid.fill(HealthState::Empty);
+        let empty = count_states(&grid);
+        assert_eq!(empty.total(), 0);
+        assert_eq!(empty.fractions(), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_maths_population_stats_approx_eq_case1() {
+        let stats = PopulationStats { susceptible: 600, infected: 100, recovered: 300 };
+        assert!(stats.approx_eq(&stats, 0.0));
+        // Same fractions at a different population size
+        assert!(stats.approx_eq(&PopulationStats { susceptible: 1200, infected: 200, recovered: 600 }, 0.0));
+        let empty = PopulationStats { susceptible: 0, infected: 0, recovered: 0 };
+        assert!(empty.approx_eq(&empty, 0.0));
+    }
+
+    #[test]
+    fn test_maths_population_stats_approx_eq_case2() {
+        let stats = PopulationStats { susceptible: 600, infected: 100, recovered: 300 };
+        // Infected fraction 0.104 vs 0.1: about 3.8% apart
+        let close = PopulationStats { susceptible: 596, infected: 104, recovered: 300 };
+        assert!(stats.approx_eq(&close, 0.05));
+        assert!(close.approx_eq(&stats, 0.05));
+        assert!(!stats.approx_eq(&close, 0.03));
+
+        let no_infected = PopulationStats { susceptible: 700, infected: 0, recovered: 300 };
+        assert!(!stats.approx_eq(&no_infected, 0.5));
+    }
+
+    #[test]
+    fn test_maths_infection_kernel_new_case1() {
+        let kernel = InfectionKernel::new(3, vec![0.5, 1.0, 0.5, 1.0, 9.0, 1.0, 0.5, 0.0, 0.5]).unwrap();
+        assert_eq!(kernel.size(), 3);
+        let offsets: Vec<(isize, isize, f64)> = kernel.offsets().collect();
+        // Centre and zero entries are skipped
+        assert_eq!(offsets, [(-1, -1, 0.5), (0, -1, 1.0), (1, -1, 0.5), (-1, 0, 1.0), (1, 0, 1.0), (-1, 1, 0.5), (1, 1, 0.5)]);
+
+        assert!(matches!(InfectionKernel::new(4, vec![1.0; 16]), Err(SirError::InvalidParam { name: "kernel size", .. })));
+        assert!(matches!(InfectionKernel::new(3, vec![1.0; 8]), Err(SirError::Grid(GridError::LengthMismatch { expected: 9, found: 8 }))));
+        let mut negative = vec![1.0; 9];
+        negative[2] = -0.5;
+        assert!(matches!(InfectionKernel::new(3, negative), Err(SirError::InvalidParam { name: "kernel weight", .. })));
+    }
+
+    #[test]
+    fn test_maths_infection_kernel_anisotropic_case1() {
+        let moore = InfectionKernel::new(3, vec![1.0; 9]).unwrap();
+        assert!(InfectionKernel::anisotropic(1.0, 1.0).unwrap().offsets().eq(moore.offsets()));
+        let offsets: Vec<(isize, isize, f64)> = InfectionKernel::anisotropic(4.0, 1.0).unwrap().offsets().collect();
+        assert_eq!(offsets, [(-1, -1, 2.0), (0, -1, 1.0), (1, -1, 2.0), (-1, 0, 4.0), (1, 0, 4.0), (-1, 1, 2.0), (0, 1, 1.0), (1, 1, 2.0)]);
+        assert!(matches!(InfectionKernel::anisotropic(1.0, -1.0), Err(SirError::InvalidParam { .. })));
+    }
+
+    #[test]
+    fn test_maths_reported_stats_case1() {
+        let mut history = History::new();
+        for recovered in [0, 4, 10, 18, 25, 30, 33] {
+            history.push(PopulationStats { susceptible: 60 - recovered.min(30), infected: 40 - recovered + recovered.min(30), recovered });
+        }
+        let reported = history.reported_stats(5, 3).unwrap();
+        assert_eq!(reported.recovered, history.days[2].recovered);
+        assert_eq!(reported.total(), history.days[5].total());
+        assert_eq!(reported.susceptible, history.days[5].susceptible);
+        assert_eq!(history.reported_stats(1, 3).unwrap().recovered, 0);
+        assert_eq!(history.reported_stats(4, 0), Some(history.days[4]));
+        assert_eq!(history.reported_stats(7, 3), None);
+    }
+
+    #[test]
+    fn test_maths_epidemic_feasible_case1() {
+        assert!(SirParams::default().epidemic_feasible());
+        assert!(!SirParams { beta: 0.0, ..SirParams::default() }.epidemic_feasible());
+        assert!(!SirParams { i_ratio: 0.0, ..SirParams::default() }.epidemic_feasible());
+        assert!(!SirParams { dt: 0.0, ..SirParams::default() }.epidemic_feasible());
+        assert!(!SirParams { beta_field: Some(vec![0.0; 4]), ..SirParams::default() }.epidemic_feasible());
+        assert!(SirParams { beta: 0.0, beta_field: Some(vec![0.0, 0.2]), ..SirParams::default() }.epidemic_feasible());
+        let centre_only = InfectionKernel::new(3, vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]).unwrap();
+        assert!(!SirParams { kernel: Some(centre_only), ..SirParams::default() }.epidemic_feasible());
+        assert!(SirParams { i_ratio: 0.0, ..SirParams::default() }.can_transmit());
+    }
+
+    #[test]
+    fn test_maths_morans_i_case1() {
+        let params = SirParams { i_ratio: 0.0, ..SirParams::default() };
+        let mut clustered = Grid::init(30, 30, &params);
+        for ((x, y), _) in Grid::init(30, 30, &params).iter_cells() {
+            if x < 15 {
+                clustered.write(clustered.get_index(x, y), HealthState::Infected);
+            }
+        }
+        assert!(morans_i(&clustered) > 0.8, "{}", morans_i(&clustered));
+
+        let scattered = Grid::init_seeded(30, 30, &SirParams { i_ratio: 0.5, ..SirParams::default() }, 4);
+        assert!(morans_i(&scattered).abs() < 0.1, "{}", morans_i(&scattered));
+
+        let mut checkerboard = Grid::init(30, 30, &params);
+        for idx in (0..900).filter(|idx| (idx % 30 + idx / 30) % 2 == 0) {
+            checkerboard.write(idx, HealthState::Infected);
+        }
+        assert!(morans_i(&checkerboard) < 0.0);
+        assert_eq!(morans_i(&Grid::init(30, 30, &params)), 0.0);
+    }
+}
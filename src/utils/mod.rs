@@ -1,6 +1,11 @@
 // Contains copde that points tothe correct lib
 pub mod simulation;
 pub mod maths;
-pub mod grid:
-pub mod test;
+pub mod grid;
+pub mod error;
+pub mod layers;
+pub mod network;
+pub mod render;
+pub mod rng;
+
 
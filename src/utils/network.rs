@@ -0,0 +1,135 @@
+﻿use rand::Rng;
+use crate::utils::grid::HealthState;
+use crate::utils::maths::{PopulationStats, SirParams};
+use crate::utils::simulation::{process_infected, process_recovered};
+
+/// SIR on an explicit contact network: each node has an adjacency list instead
+/// of lattice neighbors. Transitions follow the grid model, with a node's
+/// degree as the infection divisor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkSir {
+    pub states: Vec<HealthState>,
+    pub adjacency: Vec<Vec<usize>>,
+}
+
+impl NetworkSir {
+    /// All-susceptible network of `node_count` nodes joined by undirected `edges`.
+    /// Self-loops and duplicate edges are ignored.
+    pub fn from_edges(node_count: usize, edges: &[(usize, usize)]) -> Self {
+        let mut adjacency = vec![Vec::new(); node_count];
+        for &(a, b) in edges {
+            assert!(a < node_count && b < node_count, "Edge ({}, {}) references a missing node", a, b);
+            if a != b && !adjacency[a].contains(&b) {
+                adjacency[a].push(b);
+                adjacency[b].push(a);
+            }
+        }
+        NetworkSir { states: vec![HealthState::Susceptible; node_count], adjacency }
+    }
+
+    /// All-susceptible Erdős–Rényi graph: each pair of nodes is joined with probability `p`.
+    pub fn erdos_renyi(node_count: usize, p: f64, rng: &mut impl Rng) -> Self {
+        let mut edges = Vec::new();
+        for a in 0..node_count {
+            for b in a + 1..node_count {
+                if rng.r#gen::<f64>() < p {
+                    edges.push((a, b));
+                }
+            }
+        }
+        Self::from_edges(node_count, &edges)
+    }
+
+    /// Mark `node` as infected.
+    pub fn infect(&mut self, node: usize) {
+        self.states[node] = HealthState::Infected;
+    }
+
+    /// Count how many of `node`'s neighbors are infected.
+    pub fn count_infected_neighbors(&self, node: usize) -> usize {
+        self.adjacency[node]
+            .iter()
+            .filter(|&&neighbor| self.states[neighbor] == HealthState::Infected)
+            .count()
+    }
+
+    /// Probability that susceptible `node` becomes infected this step.
+    pub fn infection_probability(&self, node: usize, params: &SirParams) -> f64 {
+        let degree = self.adjacency[node].len();
+        if degree == 0 {
+            return 0.0;
+        }
+        params.beta * (self.count_infected_neighbors(node) as f64 / degree as f64) * params.dt
+    }
+
+    /// Advance every node one step. Returns the number of new infections.
+    pub fn step(&mut self, params: &SirParams, rng: &mut impl Rng) -> usize {
+        let mut new_infections = 0;
+        let next: Vec<HealthState> = (0..self.states.len())
+            .map(|node| match self.states[node] {
+                HealthState::Susceptible => {
+                    if rng.r#gen::<f64>() < self.infection_probability(node, params) {
+                        new_infections += 1;
+                        HealthState::Infected
+                    } else {
+                        HealthState::Susceptible
+                    }
+                }
+                HealthState::Infected  => process_infected(params, rng),
+                HealthState::Recovered => process_recovered(params, rng),
+                HealthState::Empty     => HealthState::Empty,
+            })
+            .collect();
+        self.states = next;
+        new_infections
+    }
+
+    /// Count how many nodes are in each state.
+    pub fn counts(&self) -> PopulationStats {
+        let count = |state: HealthState| self.states.iter().filter(|&&s| s == state).count();
+        PopulationStats {
+            susceptible: count(HealthState::Susceptible),
+            infected: count(HealthState::Infected),
+            recovered: count(HealthState::Recovered),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    // One infected corner of a triangle infects the other two with certainty
+    fn test_network_step_case1() {
+        let params = SirParams { beta: 2.0, gamma: 0.0, dt: 1.0, ..SirParams::default() };
+        let mut network = NetworkSir::from_edges(3, &[(0, 1), (1, 2), (2, 0)]);
+        network.infect(0);
+        let mut rng = StdRng::seed_from_u64(1);
+
+        assert_eq!(network.step(&params, &mut rng), 2);
+        assert_eq!(network.counts(), PopulationStats { susceptible: 0, infected: 3, recovered: 0 });
+    }
+
+    #[test]
+    // Isolated nodes can't be infected
+    fn test_network_step_case2() {
+        let params = SirParams { beta: 2.0, gamma: 0.0, dt: 1.0, ..SirParams::default() };
+        let mut network = NetworkSir::from_edges(3, &[(0, 1), (1, 1), (0, 1)]);
+        network.infect(0);
+        assert_eq!(network.adjacency[1], vec![0]);
+        network.step(&params, &mut StdRng::seed_from_u64(1));
+        assert_eq!(network.states, vec![HealthState::Infected, HealthState::Infected, HealthState::Susceptible]);
+    }
+
+    #[test]
+    fn test_network_erdos_renyi_case1() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let complete = NetworkSir::erdos_renyi(6, 1.0, &mut rng);
+        assert!(complete.adjacency.iter().all(|neighbors| neighbors.len() == 5));
+        let empty = NetworkSir::erdos_renyi(6, 0.0, &mut rng);
+        assert!(empty.adjacency.iter().all(Vec::is_empty));
+    }
+}
@@ -0,0 +1,250 @@
+﻿use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use crate::utils::grid::{Grid, HealthState};
+use crate::utils::layers::NEVER_INFECTED;
+
+/// RGB colour drawn for each health state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub susceptible: [u8; 3],
+    pub infected: [u8; 3],
+    pub recovered: [u8; 3],
+    pub empty: [u8; 3],
+}
+
+impl Default for Palette {
+    /// Grey, red and green on black.
+    fn default() -> Self {
+        Palette {
+            susceptible: [200, 200, 200],
+            infected: [220, 40, 40],
+            recovered: [40, 160, 70],
+            empty: [0, 0, 0],
+        }
+    }
+}
+
+impl Palette {
+    /// Okabe-Ito colours that stay distinct under the common colour-vision deficiencies.
+    pub fn colorblind_safe() -> Self {
+        Palette {
+            susceptible: [200, 200, 200],
+            infected: [213, 94, 0],
+            recovered: [0, 114, 178],
+            empty: [0, 0, 0],
+        }
+    }
+
+    /// Colours for hand-drawn grid templates (see `Grid::from_image`):
+    /// green susceptible, red infected, grey recovered on black.
+    pub fn template() -> Self {
+        Palette {
+            susceptible: [0, 255, 0],
+            infected: [255, 0, 0],
+            recovered: [128, 128, 128],
+            empty: [0, 0, 0],
+        }
+    }
+
+    /// State whose colour is nearest to `color`, if it lies within `tolerance`
+    /// (Euclidean distance in RGB). Ties go to the earlier of S, I, R, Empty.
+    pub fn nearest_state(&self, color: [u8; 3], tolerance: f64) -> Option<HealthState> {
+        let distance = |state: HealthState| {
+            let target = self.color(state);
+            (0..3).map(|c| (color[c] as f64 - target[c] as f64).powi(2)).sum::<f64>().sqrt()
+        };
+        [HealthState::Susceptible, HealthState::Infected, HealthState::Recovered, HealthState::Empty]
+            .into_iter()
+            .map(|state| (state, distance(state)))
+            .filter(|&(_, d)| d <= tolerance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(state, _)| state)
+    }
+
+    /// Colour for `state`.
+    pub fn color(&self, state: HealthState) -> [u8; 3] {
+        match state {
+            HealthState::Susceptible => self.susceptible,
+            HealthState::Infected    => self.infected,
+            HealthState::Recovered   => self.recovered,
+            HealthState::Empty       => self.empty,
+        }
+    }
+}
+
+/// Flat, opaque RGBA buffer of the grid, row-major, as used by canvas `ImageData`.
+pub fn render_rgba(grid: &Grid, palette: &Palette) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(grid.grid_x * grid.grid_y * 4);
+    for (_, state) in grid.iter_cells() {
+        let [r, g, b] = palette.color(state);
+        pixels.extend_from_slice(&[r, g, b, 255]);
+    }
+    pixels
+}
+
+/// Write the grid's states as a binary PPM (P6) image, one pixel per cell.
+pub fn write_ppm(writer: &mut impl Write, grid: &Grid, palette: &Palette) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", grid.grid_x, grid.grid_y)?;
+    let mut pixels = Vec::with_capacity(grid.grid_x * grid.grid_y * 3);
+    for (_, state) in grid.iter_cells() {
+        pixels.extend_from_slice(&palette.color(state));
+    }
+    writer.write_all(&pixels)
+}
+
+/// Destination for grids captured one at a time as a run progresses, so
+/// frames don't all have to be held in memory.
+pub trait FrameSink {
+    fn write_frame(&mut self, grid: &Grid) -> io::Result<()>;
+}
+
+/// Writes each frame to its own PPM file, `frame_0001.ppm`, `frame_0002.ppm`, ...
+pub struct PpmSequence {
+    dir: PathBuf,
+    palette: Palette,
+    frames: usize,
+}
+
+impl PpmSequence {
+    /// Sequence writing into `dir`, which is created if missing.
+    pub fn new(dir: impl AsRef<Path>, palette: Palette) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(PpmSequence { dir: dir.as_ref().to_path_buf(), palette, frames: 0 })
+    }
+
+    /// Number of frames written so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Path of the `n`th frame, counting from 1.
+    pub fn frame_path(&self, n: usize) -> PathBuf {
+        self.dir.join(format!("frame_{:04}.ppm", n))
+    }
+}
+
+impl FrameSink for PpmSequence {
+    fn write_frame(&mut self, grid: &Grid) -> io::Result<()> {
+        let file = std::fs::File::create(self.frame_path(self.frames + 1))?;
+        let mut writer = io::BufWriter::new(file);
+        write_ppm(&mut writer, grid, &self.palette)?;
+        writer.flush()?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// Colour for a cell first infected on `day`, on a gradient from blue (day 0)
+/// through green to red (`max_day`).
+pub fn day_color(day: u32, max_day: u32) -> [u8; 3] {
+    let t = if max_day == 0 { 0.0 } else { day as f64 / max_day as f64 };
+    let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    if t < 0.5 {
+        [0, channel(t * 2.0), channel(1.0 - t * 2.0)]
+    } else {
+        [channel(t * 2.0 - 1.0), channel(2.0 - t * 2.0), 0]
+    }
+}
+
+/// Write an infection-day map (see `Simulation::infection_day_map`) as a binary
+/// PPM (P6) image. Cells never infected are drawn black; the rest follow `day_color`,
+/// scaled to the latest infection day.
+pub fn write_infection_day_ppm(writer: &mut impl Write, map: &[u32], width: usize, height: usize) -> io::Result<()> {
+    if map.len() != width * height {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Map size does not match image dimensions"));
+    }
+    let max_day = map.iter().copied().filter(|&day| day != NEVER_INFECTED).max().unwrap_or(0);
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    let mut pixels = Vec::with_capacity(map.len() * 3);
+    for &day in map {
+        let color = if day == NEVER_INFECTED { [0, 0, 0] } else { day_color(day, max_day) };
+        pixels.extend_from_slice(&color);
+    }
+    writer.write_all(&pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::maths::SirParams;
+
+    fn one_of_each() -> Grid {
+        let mut grid = Grid::init(2, 2, &SirParams { i_ratio: 0.0, ..SirParams::default() });
+        grid.write(1, HealthState::Infected);
+        grid.write(2, HealthState::Recovered);
+        grid.write(3, HealthState::Empty);
+        grid
+    }
+
+    #[test]
+    fn test_render_write_ppm_case1() {
+        let palette = Palette { susceptible: [1, 2, 3], infected: [4, 5, 6], recovered: [7, 8, 9], empty: [10, 11, 12] };
+        let mut out = Vec::new();
+        write_ppm(&mut out, &one_of_each(), &palette).unwrap();
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(&out[header.len()..], &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn test_render_render_rgba_case1() {
+        let palette = Palette::colorblind_safe();
+        let pixels = render_rgba(&one_of_each(), &palette);
+        assert_eq!(pixels.len(), 16);
+        assert_eq!(&pixels[4..8], &[213, 94, 0, 255]);
+        assert_eq!(&pixels[8..12], &[0, 114, 178, 255]);
+        assert_ne!(render_rgba(&one_of_each(), &Palette::default()), pixels);
+    }
+
+    #[test]
+    fn test_render_day_color_case1() {
+        assert_eq!(day_color(0, 10), [0, 0, 255]);
+        assert_eq!(day_color(5, 10), [0, 255, 0]);
+        assert_eq!(day_color(10, 10), [255, 0, 0]);
+        assert_eq!(day_color(0, 0), [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_render_write_infection_day_ppm_case1() {
+        let map = [0, 2, NEVER_INFECTED, 1, 2, 2];
+        let mut out = Vec::new();
+        write_infection_day_ppm(&mut out, &map, 3, 2).unwrap();
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        let pixels = &out[header.len()..];
+        assert_eq!(pixels.len(), 18);
+        assert_eq!(&pixels[0..3], &[0, 0, 255]);
+        assert_eq!(&pixels[3..6], &[255, 0, 0]);
+        assert_eq!(&pixels[6..9], &[0, 0, 0]);
+        assert!(write_infection_day_ppm(&mut Vec::new(), &map, 2, 2).is_err());
+    }
+
+    #[test]
+    fn test_render_nearest_state_case1() {
+        let palette = Palette::template();
+        assert_eq!(palette.nearest_state([0, 255, 0], 0.0), Some(HealthState::Susceptible));
+        assert_eq!(palette.nearest_state([240, 12, 8], 30.0), Some(HealthState::Infected));
+        assert_eq!(palette.nearest_state([120, 135, 125], 30.0), Some(HealthState::Recovered));
+        assert_eq!(palette.nearest_state([10, 10, 10], 30.0), Some(HealthState::Empty));
+        assert_eq!(palette.nearest_state([240, 12, 8], 10.0), None);
+        assert_eq!(palette.nearest_state([0, 0, 255], 100.0), None);
+    }
+
+    #[test]
+    fn test_render_ppm_sequence_case1() {
+        let dir = std::env::temp_dir().join(format!("sir_model_{}_frames", std::process::id()));
+        let mut sink = PpmSequence::new(&dir, Palette::default()).unwrap();
+        for _ in 0..3 {
+            sink.write_frame(&one_of_each()).unwrap();
+        }
+        assert_eq!(sink.frames(), 3);
+        assert_eq!(sink.frame_path(2), dir.join("frame_0002.ppm"));
+        let mut expected = Vec::new();
+        write_ppm(&mut expected, &one_of_each(), &Palette::default()).unwrap();
+        for n in 1..=3 {
+            assert_eq!(std::fs::read(sink.frame_path(n)).unwrap(), expected);
+        }
+        assert!(!sink.frame_path(4).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+﻿use rand::RngCore;
+
+/// Wraps an RNG and keeps a copy of every 64-bit word drawn from it, so the run
+/// can be replayed exactly with `ReplayRng`. Each `r#gen::<f64>()` draws one word.
+pub struct RecordingRng<R: RngCore> {
+    inner: R,
+    tape: Vec<u64>,
+}
+
+impl<R: RngCore> RecordingRng<R> {
+    pub fn new(inner: R) -> Self {
+        RecordingRng { inner, tape: Vec::new() }
+    }
+
+    /// Every word drawn so far, in order.
+    pub fn tape(&self) -> &[u64] {
+        &self.tape
+    }
+
+    /// The recorded words as the `f64` in [0, 1) that `r#gen::<f64>()` makes of each.
+    pub fn f64_draws(&self) -> impl Iterator<Item = f64> + '_ {
+        self.tape.iter().map(|&word| (word >> 11) as f64 * (1.0 / (1u64 << 53) as f64))
+    }
+
+    /// Stop recording and return the tape, ready for `ReplayRng::new`.
+    pub fn into_tape(self) -> Vec<u64> {
+        self.tape
+    }
+}
+
+impl<R: RngCore> RngCore for RecordingRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let word = self.inner.next_u64();
+        self.tape.push(word);
+        word
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Feeds back a tape recorded by `RecordingRng`, word for word. Panics if the
+/// replay draws more than was recorded, which means it has diverged.
+pub struct ReplayRng {
+    tape: Vec<u64>,
+    position: usize,
+}
+
+impl ReplayRng {
+    pub fn new(tape: Vec<u64>) -> Self {
+        ReplayRng { tape, position: 0 }
+    }
+
+    /// Words not yet replayed.
+    pub fn remaining(&self) -> usize {
+        self.tape.len() - self.position
+    }
+}
+
+impl RngCore for ReplayRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let word = *self.tape.get(self.position).unwrap_or_else(|| {
+            panic!("Replay ran past the end of the {}-word recording", self.tape.len())
+        });
+        self.position += 1;
+        word
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::grid::Grid;
+    use crate::utils::maths::SirParams;
+    use crate::utils::simulation::step_grid_with_rng;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_rng_replay_case1() {
+        let params = SirParams { beta: 0.5, gamma: 0.2, i_ratio: 0.1, waning_rate: 0.05, ..SirParams::default() };
+        let start = Grid::init_seeded(20, 15, &params, 9);
+
+        let mut recorded = start.clone();
+        let mut recorder = RecordingRng::new(StdRng::seed_from_u64(4));
+        for _ in 0..10 {
+            step_grid_with_rng(&mut recorded, &params, &mut recorder);
+        }
+
+        let mut replayed = start.clone();
+        let mut replayer = ReplayRng::new(recorder.into_tape());
+        for _ in 0..10 {
+            step_grid_with_rng(&mut replayed, &params, &mut replayer);
+        }
+        assert_eq!(replayed, recorded);
+        assert_eq!(replayer.remaining(), 0);
+    }
+
+    #[test]
+    fn test_rng_recording_f64_draws_case1() {
+        let mut recorder = RecordingRng::new(StdRng::seed_from_u64(1));
+        let drawn: Vec<f64> = (0..5).map(|_| recorder.r#gen::<f64>()).collect();
+        assert_eq!(recorder.tape().len(), 5);
+        assert_eq!(recorder.f64_draws().collect::<Vec<_>>(), drawn);
+    }
+
+    #[test]
+    #[should_panic(expected = "past the end")]
+    fn test_rng_replay_case2() {
+        let mut replayer = ReplayRng::new(vec![1, 2]);
+        replayer.next_u64();
+        replayer.next_u64();
+        replayer.next_u64();
+    }
+}
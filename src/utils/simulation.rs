@@ -1,209 +1,2969 @@
-use rand::Rng;
-use crate::utils::grid::{Grid, HealthState, Tile, tile_grid};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::utils::error::SirError;
+use crate::utils::grid::{Grid, GridError, GridMemory, HealthState, Neighborhood, Tile, optimal_tile_size, tile_grid};
+use crate::utils::layers::CellLayers;
 
-use crate::utils::maths::SirParams;
+use crate::utils::maths::{ContactModel, History, OdeSir, PopulationStats, SirParams, TerminationReason, TransitionCounts, count_states_fast, is_finished};
+use crate::utils::render::FrameSink;
 
 /// Count how many infected neighbors are around (x, y)
-fn count_infected_neighbors(grid: &Grid, x: usize, y: usize) -> usize {
+pub fn count_infected_neighbors(grid: &Grid, x: usize, y: usize, neighborhood: Neighborhood) -> usize {
+    grid.infected_neighbor_count(x, y, neighborhood).0
+}
+
+/// Count the infected neighbors around (x, y) that can transmit, skipping quarantined cells
+pub fn count_transmitting_neighbors(grid: &Grid, layers: &CellLayers, x: usize, y: usize, neighborhood: Neighborhood) -> usize {
     let mut buffer = [(0, 0); 8]; // Pre-allocate buffer for neighbor coordinates
-    let count = grid.get_neighbors(x, y, &mut buffer); // Get neighbor coordinates
+    let count = grid.get_neighbors_in(x, y, neighborhood, &mut buffer); // Get neighbor coordinates
 
     // Count how many neighbors are infected
     buffer.iter()
         .take(count) // Only process valid neighbors
         .filter(|&&(nx, ny)| {
             let n_idx = grid.get_index(nx, ny);
-            grid.read(n_idx) == HealthState::Infected
+            grid.read(n_idx) == HealthState::Infected && !layers.is_quarantined(n_idx)
         })
         .count()
 }
 
+/// Count how many neighbors around (x, y) are uninhabited
+pub fn count_empty_neighbors(grid: &Grid, x: usize, y: usize, neighborhood: Neighborhood) -> usize {
+    let mut buffer = [(0, 0); 8];
+    let count = grid.get_neighbors_in(x, y, neighborhood, &mut buffer);
+
+    buffer.iter()
+        .take(count)
+        .filter(|&&(nx, ny)| grid.read(grid.get_index(nx, ny)) == HealthState::Empty)
+        .count()
+}
+
+/// Count transmitting-infected and empty cells within `radius` of (x, y)
+fn count_neighbors_within(grid: &Grid, layers: &CellLayers, x: usize, y: usize, neighborhood: Neighborhood, radius: usize) -> (usize, usize) {
+    let (mut infected, mut empty) = (0, 0);
+    for (dx, dy) in neighborhood.offsets_within_at(radius, y) {
+        let nx = x as isize + dx;
+        let ny = y as isize + dy;
+        if nx >= 0 && nx < grid.grid_x as isize && ny >= 0 && ny < grid.grid_y as isize {
+            let n_idx = grid.get_index(nx as usize, ny as usize);
+            match grid.read(n_idx) {
+                HealthState::Infected if !layers.is_quarantined(n_idx) => infected += 1,
+                HealthState::Empty => empty += 1,
+                _ => {}
+            }
+        }
+    }
+    (infected, empty)
+}
+
+/// (infected, size) of a cell's neighborhood from neighbor counts.
+/// The size is the neighborhood's `max_neighbors`; empty neighbors are not
+/// part of the neighborhood, so they shrink it.
+fn neighbor_weights(infected_neighbors: usize, empty_neighbors: usize, max_neighbors: usize) -> (f64, f64) {
+    (infected_neighbors as f64, (max_neighbors - empty_neighbors) as f64)
+}
+
+/// Weighted (infected, size) of the neighborhood.
+/// With a `kernel` each neighbor position counts its kernel weight; otherwise
+/// positions come from `neighborhood` and `radius` and count `1 / distance`
+/// with `distance_weighted`, else 1. An infected neighbor's share is further
+/// scaled by its infectiousness profile.
+fn weighted_neighbor_weights(grid: &Grid, layers: &CellLayers, x: usize, y: usize, params: &SirParams) -> (f64, f64) {
+    with_weighted_offsets(params, y, |offsets| weighted_sums_over(grid, layers, x, y, params, offsets))
+}
+
+/// Call `f` with the (dx, dy, weight) positions around a cell in row `y`, as
+/// described for `weighted_neighbor_weights`.
+fn with_weighted_offsets<T>(params: &SirParams, y: usize, f: impl FnOnce(&mut dyn Iterator<Item = (isize, isize, f64)>) -> T) -> T {
+    match &params.kernel {
+        Some(kernel) => f(&mut kernel.offsets()),
+        None => {
+            let neighborhood = params.neighborhood;
+            f(&mut neighborhood.offsets_within_at(params.radius, y).map(|(dx, dy)| {
+                let weight = if params.distance_weighted { 1.0 / neighborhood.distance_at(dx, dy, y) } else { 1.0 };
+                (dx, dy, weight)
+            }))
+        }
+    }
+}
+
+/// Transmitting infected cells around (x, y), each with its share of the
+/// infection pressure on (x, y); the shares sum to 1 unless there are none.
+/// Used to attribute a new infection to its likely sources.
+fn infector_shares(grid: &Grid, layers: &CellLayers, x: usize, y: usize, params: &SirParams) -> Vec<(usize, f64)> {
+    let mut sources = Vec::new();
+    with_weighted_offsets(params, y, |offsets| {
+        for (dx, dy, weight) in offsets {
+            let nx = x as isize + dx;
+            let ny = y as isize + dy;
+            if nx >= 0 && nx < grid.grid_x as isize && ny >= 0 && ny < grid.grid_y as isize {
+                let n_idx = grid.get_index(nx as usize, ny as usize);
+                if grid.read(n_idx) == HealthState::Infected && !layers.is_quarantined(n_idx) {
+                    sources.push((n_idx, weight * layers.infectiousness(n_idx, &params.infectiousness)));
+                }
+            }
+        }
+    });
+    let total: f64 = sources.iter().map(|&(_, weight)| weight).sum();
+    if total > 0.0 {
+        for (_, weight) in &mut sources {
+            *weight /= total;
+        }
+    }
+    sources
+}
+
+/// Infected weight and weight of all inhabited positions over the
+/// (dx, dy, weight) `offsets` of (x, y). Out-of-bounds positions count as
+/// inhabited and uninfected, as in `neighbor_weights`.
+fn weighted_sums_over(
+    grid: &Grid,
+    layers: &CellLayers,
+    x: usize,
+    y: usize,
+    params: &SirParams,
+    offsets: impl Iterator<Item = (isize, isize, f64)>,
+) -> (f64, f64) {
+    let mut infected_weight = 0.0;
+    let mut total_weight = 0.0;
+    for (dx, dy, weight) in offsets {
+        let nx = x as isize + dx;
+        let ny = y as isize + dy;
+        if nx >= 0 && nx < grid.grid_x as isize && ny >= 0 && ny < grid.grid_y as isize {
+            let n_idx = grid.get_index(nx as usize, ny as usize);
+            match grid.read(n_idx) {
+                HealthState::Empty => continue,
+                HealthState::Infected if !layers.is_quarantined(n_idx) => {
+                    infected_weight += weight * layers.infectiousness(n_idx, &params.infectiousness)
+                }
+                _ => {}
+            }
+        }
+        total_weight += weight;
+    }
+    (infected_weight, total_weight)
+}
+
+/// Infection pressure on (x, y): the share of its neighborhood that is infected, in [0, 1].
+pub fn infection_pressure(grid: &Grid, x: usize, y: usize, params: &SirParams) -> f64 {
+    infection_pressure_with(grid, &CellLayers::default(), x, y, params)
+}
+
+/// `infection_pressure`, taking per-cell layers (quarantine) into account.
+pub fn infection_pressure_with(grid: &Grid, layers: &CellLayers, x: usize, y: usize, params: &SirParams) -> f64 {
+    let (infected, size) = infection_weights(grid, layers, x, y, params);
+    if size == 0.0 { 0.0 } else { infected / size }
+}
+
+/// (infected, size) of the neighborhood of (x, y): how many transmitting
+/// infected neighbors it has and how many inhabited positions, both weighted
+/// when `params` asks for it. Recovered neighbors add `recovered_shielding`
+/// to the size on top of their own weight.
+fn infection_weights(grid: &Grid, layers: &CellLayers, x: usize, y: usize, params: &SirParams) -> (f64, f64) {
+    let (infected, size) = unshielded_infection_weights(grid, layers, x, y, params);
+    if params.recovered_shielding > 0.0 {
+        (infected, size + params.recovered_shielding * recovered_weight(grid, x, y, params))
+    } else {
+        (infected, size)
+    }
+}
+
+/// Weight of the recovered positions around (x, y), over the same offsets and
+/// weights as the infected ones.
+fn recovered_weight(grid: &Grid, x: usize, y: usize, params: &SirParams) -> f64 {
+    with_weighted_offsets(params, y, |offsets| {
+        offsets
+            .filter(|&(dx, dy, _)| {
+                let nx = x as isize + dx;
+                let ny = y as isize + dy;
+                nx >= 0
+                    && nx < grid.grid_x as isize
+                    && ny >= 0
+                    && ny < grid.grid_y as isize
+                    && grid.read(grid.get_index(nx as usize, ny as usize)) == HealthState::Recovered
+            })
+            .map(|(_, _, weight)| weight)
+            .sum()
+    })
+}
+
+/// `infection_weights` without `recovered_shielding`.
+fn unshielded_infection_weights(grid: &Grid, layers: &CellLayers, x: usize, y: usize, params: &SirParams) -> (f64, f64) {
+    let max_neighbors = params.neighborhood.max_neighbors_within(params.radius);
+    if params.kernel.is_some() || params.distance_weighted || !params.infectiousness.is_flat() {
+        weighted_neighbor_weights(grid, layers, x, y, params)
+    } else if params.radius == 1 {
+        let infected_neighbors = count_transmitting_neighbors(grid, layers, x, y, params.neighborhood);
+        let empty_neighbors = count_empty_neighbors(grid, x, y, params.neighborhood);
+        neighbor_weights(infected_neighbors, empty_neighbors, max_neighbors)
+    } else {
+        let (infected_neighbors, empty_neighbors) =
+            count_neighbors_within(grid, layers, x, y, params.neighborhood, params.radius);
+        neighbor_weights(infected_neighbors, empty_neighbors, max_neighbors)
+    }
+}
+
+/// Probability that the susceptible cell at (x, y) becomes infected this step
+pub fn infection_probability(grid: &Grid, x: usize, y: usize, params: &SirParams) -> f64 {
+    infection_probability_with(grid, &CellLayers::default(), x, y, params)
+}
+
+/// `infection_probability`, taking per-cell layers (quarantine) into account.
+/// Infected neighbors combine as `params.contact_model` says; the result is
+/// clamped to [0, 1].
+pub fn infection_probability_with(grid: &Grid, layers: &CellLayers, x: usize, y: usize, params: &SirParams) -> f64 {
+    let beta = params.beta_at(grid.get_index(x, y));
+    let probability = match params.contact_model {
+        ContactModel::Linear => (beta * infection_pressure_with(grid, layers, x, y, params)) * params.dt,
+        ContactModel::Saturating => {
+            let (infected, size) = infection_weights(grid, layers, x, y, params);
+            if size == 0.0 {
+                0.0
+            } else {
+                let per_contact = (beta * params.dt / size).clamp(0.0, 1.0);
+                1.0 - (1.0 - per_contact).powf(infected)
+            }
+        }
+    };
+    probability.clamp(0.0, 1.0)
+}
+
+/// Determine if a susceptible cell is vaccinated this step
+fn is_vaccinated(params: &SirParams, rng: &mut impl Rng) -> bool {
+    params.vaccination_rate > 0.0 && rng.r#gen::<f64>() < params.vaccination_rate * params.dt
+}
+
 /// Determine if a susceptible cell should become infected
-fn process_susceptible(grid: &Grid, x: usize, y: usize, params: &SirParams) -> HealthState {
-    let infected_neighbors = count_infected_neighbors(grid, x, y);
-    let infection_probability = (params.beta * infected_neighbors as f64 / 8.0) * params.dt;
-    if rand::thread_rng().r#gen::<f64>() < infection_probability {
+pub fn process_susceptible(grid: &Grid, x: usize, y: usize, params: &SirParams, rng: &mut impl Rng) -> HealthState {
+    let infection_probability = infection_probability(grid, x, y, params);
+    if rng.r#gen::<f64>() < infection_probability {
         HealthState::Infected
     } else {
         HealthState::Susceptible
     }
 }
 
-fn process_infected(params: &SirParams) -> HealthState {
-    if rand::thread_rng().r#gen::<f64>() < params.gamma * params.dt {
+/// Determine if a recovered cell at (x, y) is reinfected, scaling its
+/// infection probability by `reinfection_factor`
+fn is_reinfected(grid: &Grid, layers: &CellLayers, x: usize, y: usize, params: &SirParams, rng: &mut impl Rng) -> bool {
+    params.reinfection_factor > 0.0
+        && rng.r#gen::<f64>() < params.reinfection_factor * infection_probability_with(grid, layers, x, y, params)
+}
+
+/// Determine if a recovered cell loses its immunity (SIRS)
+pub fn process_recovered(params: &SirParams, rng: &mut impl Rng) -> HealthState {
+    if params.waning_rate > 0.0 && rng.r#gen::<f64>() < params.waning_rate * params.dt {
+        HealthState::Susceptible
+    } else {
+        HealthState::Recovered
+    }
+}
+
+pub fn process_infected(params: &SirParams, rng: &mut impl Rng) -> HealthState {
+    if rng.r#gen::<f64>() < params.gamma * params.dt {
         HealthState::Recovered
     } else {
         HealthState::Infected
     }
 }
 
-pub fn step_grid(grid: &mut Grid, params: &SirParams) {
+/// `gamma` for the infected cell at (x, y), slowed by `params.crowding_penalty`
+/// according to the share of its in-bounds neighbors that are infected.
+fn crowded_gamma(grid: &Grid, x: usize, y: usize, params: &SirParams, gamma: f64) -> f64 {
+    if params.crowding_penalty == 0.0 {
+        return gamma;
+    }
+    let (infected, total) = grid.infected_neighbor_count(x, y, params.neighborhood);
+    let fraction = if total == 0 { 0.0 } else { infected as f64 / total as f64 };
+    gamma * params.crowding_factor(fraction)
+}
+
+/// Everything `next_state` reads about a cell besides its current state.
+#[derive(Clone, Copy)]
+pub struct CellContext<'a> {
+    /// The grid as it was at the start of the step.
+    pub grid: &'a Grid,
+    pub layers: &'a CellLayers,
+    pub params: &'a SirParams,
+    pub x: usize,
+    pub y: usize,
+    /// Linear index of (x, y).
+    pub idx: usize,
+}
+
+/// State of one cell after a step. Every stochastic stepper (serial, tiled and
+/// per-cell seeded/parallel) goes through here, so they share the same dynamics.
+/// Transitions are tried in this order, each drawing from `rng` only when it
+/// can happen:
+/// 1. Susceptible: vaccination, then infection.
+/// 2. Infected: recovery, when a tracked infection timer runs out, otherwise
+///    with probability `gamma * dt` (scaled by age bracket if tracked, and
+///    by crowding if `crowding_penalty` is set).
+/// 3. Recovered: reinfection, then loss of immunity.
+///
+/// Empty cells never change. Layer bookkeeping (counting timers down, drawing
+/// new timers, quarantine) is left to the caller.
+pub fn next_state(current: HealthState, ctx: &CellContext, rng: &mut impl Rng) -> HealthState {
+    let CellContext { grid, layers, params, x, y, idx } = *ctx;
+    match current {
+        HealthState::Susceptible if is_vaccinated(params, rng) => HealthState::Recovered,
+        HealthState::Susceptible => {
+            if rng.r#gen::<f64>() < infection_probability_with(grid, layers, x, y, params) {
+                HealthState::Infected
+            } else {
+                HealthState::Susceptible
+            }
+        }
+        // The caller counts the timer down once the step is done
+        HealthState::Infected if !layers.infection_timers.is_empty() => {
+            if layers.infection_timers[idx] <= 1 { HealthState::Recovered } else { HealthState::Infected }
+        }
+        HealthState::Infected => {
+            let gamma = crowded_gamma(grid, x, y, params, params.gamma_for(layers.age_at(idx)));
+            if rng.r#gen::<f64>() < gamma * params.dt { HealthState::Recovered } else { HealthState::Infected }
+        }
+        HealthState::Recovered if is_reinfected(grid, layers, x, y, params, rng) => HealthState::Infected,
+        HealthState::Recovered => process_recovered(params, rng),
+        HealthState::Empty => HealthState::Empty,
+    }
+}
+
+/// Advance the grid one step, drawing from the thread-local RNG.
+/// Returns the number of new infections (the step's incidence).
+pub fn step_grid(grid: &mut Grid, params: &SirParams) -> usize {
+    step_grid_with_rng(grid, params, &mut rand::thread_rng())
+}
+
+/// Travel: each inhabited cell swaps states with a random other inhabited cell
+/// with probability `mobility`. Only positions change, so S/I/R counts are conserved.
+pub fn mobility_step(grid: &mut Grid, rng: &mut impl Rng, mobility: f64) {
+    mobility_step_with(grid, &mut CellLayers::default(), rng, mobility);
+}
+
+/// `mobility_step` that carries each cell's layer data along with it.
+pub fn mobility_step_with(grid: &mut Grid, layers: &mut CellLayers, rng: &mut impl Rng, mobility: f64) {
+    let total_cells = grid.grid_x * grid.grid_y;
+    if mobility <= 0.0 || total_cells < 2 {
+        return;
+    }
+    for idx in 0..total_cells {
+        if rng.r#gen::<f64>() >= mobility {
+            continue;
+        }
+        let other = rng.gen_range(0..total_cells);
+        let (a, b) = (grid.read(idx), grid.read(other));
+        if a == HealthState::Empty || b == HealthState::Empty {
+            continue;
+        }
+        grid.write(idx, b);
+        grid.write(other, a);
+        layers.swap(idx, other);
+    }
+}
+
+/// Advance the grid one step, drawing all randomness from `rng`.
+/// Returns the number of new infections (the step's incidence).
+/// Per-cell layers are not kept between calls; use `step_grid_layered` for
+/// timed infectious periods.
+pub fn step_grid_with_rng(grid: &mut Grid, params: &SirParams, rng: &mut impl Rng) -> usize {
+    step_grid_layered(grid, &mut CellLayers::default(), params, rng)
+}
+
+/// True if `step_grid_f32` can step with `params` itself: the plain model with
+/// radius-1 neighborhoods, `gamma` recovery and linear contacts, plus optional
+/// `beta_field` and Empty cells.
+fn f32_path_supported(params: &SirParams) -> bool {
+    params.radius == 1
+        && params.kernel.is_none()
+        && !params.distance_weighted
+        && params.infectiousness.is_flat()
+        && !params.infectious_period.is_timed()
+        && params.contact_model == ContactModel::Linear
+        && [
+            params.vaccination_rate,
+            params.waning_rate,
+            params.reinfection_factor,
+            params.quarantine_prob,
+            params.mobility,
+            params.recovered_shielding,
+            params.crowding_penalty,
+        ]
+            .iter()
+            .all(|&rate| rate == 0.0)
+}
+
+/// `step_grid_with_rng` doing the per-cell probability math and draws in `f32`,
+/// so each draw takes 32 bits of RNG output instead of 64. The loop is still
+/// scalar, one draw per cell; see `float_precision` in the benches. Outcomes are
+/// statistically the same as the `f64` path, not draw-for-draw identical.
+/// Parameters outside the plain model (see `f32_path_supported`) fall back to
+/// `step_grid_with_rng`. Returns the number of new infections.
+pub fn step_grid_f32(grid: &mut Grid, params: &SirParams, rng: &mut impl Rng) -> usize {
+    if !f32_path_supported(params) {
+        return step_grid_with_rng(grid, params, rng);
+    }
+    let neighborhood = params.neighborhood;
+    let max_neighbors = neighborhood.max_neighbors_within(1);
+    let dt = params.dt as f32;
+    let recovery = params.gamma as f32 * dt;
+    let current = grid.clone();
+    let mut new_infections = 0;
+
+    let mut idx = 0;
+    for y in 0..current.grid_y {
+        for x in 0..current.grid_x {
+            match current.read(idx) {
+                HealthState::Susceptible => {
+                    let infected = count_infected_neighbors(&current, x, y, neighborhood);
+                    if infected > 0 {
+                        let size = max_neighbors - count_empty_neighbors(&current, x, y, neighborhood);
+                        let probability = params.beta_at(idx) as f32 * dt * infected as f32 / size as f32;
+                        if rng.r#gen::<f32>() < probability {
+                            grid.write(idx, HealthState::Infected);
+                            new_infections += 1;
+                        }
+                    }
+                }
+                HealthState::Infected if rng.r#gen::<f32>() < recovery => grid.write(idx, HealthState::Recovered),
+                _ => {}
+            }
+            idx += 1;
+        }
+    }
+    new_infections
+}
+
+/// Advance the grid one step, updating the per-cell `layers` alongside it.
+/// Returns the number of new infections (the step's incidence).
+pub fn step_grid_layered(grid: &mut Grid, layers: &mut CellLayers, params: &SirParams, rng: &mut impl Rng) -> usize {
+    step_grid_layered_transitions(grid, layers, params, rng).new_infections()
+}
+
+/// `step_grid_layered`, returning how many cells made each transition.
+pub fn step_grid_layered_transitions(grid: &mut Grid, layers: &mut CellLayers, params: &SirParams, rng: &mut impl Rng) -> TransitionCounts {
+    layers.prepare(grid, params, rng);
+    let timed = params.infectious_period.is_timed();
+    mobility_step_with(grid, layers, rng, params.mobility);
+
     // Clone cells buffer for writing next state
     let mut new_grid = Grid {
         grid_x: grid.grid_x,
         grid_y: grid.grid_y,
         cells: grid.cells.clone(),
     };
+    let mut transitions = TransitionCounts::default();
 
+    // Row-major order, so the linear index is just a running counter
+    let mut idx = 0;
+    for y in 0..grid.grid_y {
+        for x in 0..grid.grid_x {
+            let current = grid.read(idx);
+            let updated = next_state(current, &CellContext { grid, layers, params, x, y, idx }, rng);
+            if timed && current == HealthState::Infected {
+                let timer = &mut layers.infection_timers[idx];
+                *timer = timer.saturating_sub(1);
+            }
+            transitions.record(current, updated);
+            if current != HealthState::Infected && updated == HealthState::Infected {
+                if !layers.infected_on.is_empty() {
+                    for (source, share) in infector_shares(grid, layers, x, y, params) {
+                        layers.record_transmission(source, share);
+                    }
+                }
+                layers.record_infection(idx);
+                if timed {
+                    layers.infection_timers[idx] = params.infectious_period.draw(rng);
+                }
+                // Bits are only read for infected cells, so a stale bit from an
+                // earlier infection is harmless until overwritten here
+                if params.quarantine_prob > 0.0 {
+                    let quarantined = rng.r#gen::<f64>() < params.quarantine_prob;
+                    layers.set_quarantined(idx, quarantined);
+                }
+            }
+            new_grid.write(idx, updated);
+            idx += 1;
+        }
+    }
+
+    if !layers.days_infected.is_empty() {
+        layers.advance_days_infected(grid, &new_grid);
+    }
+    *grid = new_grid;
+    layers.steps += 1;
+    transitions
+}
+
+/// Advance the grid one step with no randomness: each transition happens iff
+/// its probability exceeds `threshold` (0.5 gives majority-rule dynamics).
+/// Timed infectious periods, quarantine and mobility are ignored.
+/// Returns the number of new infections.
+pub fn step_grid_deterministic(grid: &mut Grid, params: &SirParams, threshold: f64) -> usize {
+    step_grid_deterministic_transitions(grid, params, threshold).new_infections()
+}
+
+/// `step_grid_deterministic`, returning how many cells made each transition.
+pub fn step_grid_deterministic_transitions(grid: &mut Grid, params: &SirParams, threshold: f64) -> TransitionCounts {
+    let layers = CellLayers::default();
+    let mut new_grid = grid.clone();
+    let mut transitions = TransitionCounts::default();
+
+    let mut idx = 0;
     for y in 0..grid.grid_y {
         for x in 0..grid.grid_x {
-            let idx = grid.get_index(x, y);
             let current = grid.read(idx);
             let updated = match current {
-                HealthState::Susceptible => process_susceptible(grid, x, y, params),
-                HealthState::Infected    => process_infected(params),
-                HealthState::Recovered   => HealthState::Recovered,
+                HealthState::Susceptible if params.vaccination_rate * params.dt > threshold => HealthState::Recovered,
+                HealthState::Susceptible if infection_probability_with(grid, &layers, x, y, params) > threshold => HealthState::Infected,
+                HealthState::Infected if crowded_gamma(grid, x, y, params, params.gamma) * params.dt > threshold => HealthState::Recovered,
+                HealthState::Recovered
+                    if params.reinfection_factor * infection_probability_with(grid, &layers, x, y, params) > threshold =>
+                {
+                    HealthState::Infected
+                }
+                HealthState::Recovered if params.waning_rate * params.dt > threshold => HealthState::Susceptible,
+                state => state,
             };
+            transitions.record(current, updated);
             new_grid.write(idx, updated);
+            idx += 1;
         }
     }
 
     *grid = new_grid;
+    transitions
+}
+
+/// SplitMix64 finalizer, used to derive per-cell seeds.
+fn splitmix64(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
-pub fn step_tile(tile: &Tile, params: &SirParams, output: &mut Grid) {
-    let mut buffer = [None; 8]; // Pre-allocate buffer for neighbors
-    for y in 0..tile.tile_y {
-        for x in 0..tile.tile_x {
-            let idx = output.get_index(tile.origin_x + x, tile.origin_y + y);
-            let current = tile.get_state(x, y).unwrap();
-            let count = tile.get_neighbors_healthstates(x, y, &mut buffer); // Get neighbors
+/// Small SplitMix64 generator giving one cell its draws for one day.
+/// Seeded from (seed, day, cell index) alone, so a cell's draws don't depend
+/// on which thread steps it or in what order.
+struct CellRng {
+    state: u64,
+}
 
-            // Count infected neighbors
-            let infected_neighbors = buffer.iter()
-                .take(count)
-                .filter(|&&s| s == Some(HealthState::Infected))
-                .count();
+impl CellRng {
+    fn new(seed: u64, day: usize, idx: usize) -> Self {
+        CellRng { state: splitmix64(splitmix64(splitmix64(seed) ^ day as u64) ^ idx as u64) }
+    }
+}
 
-            // Determine the new state
-            let new_state = match current {
-                HealthState::Susceptible => {
-                    let p = (params.beta * infected_neighbors as f64 / 8.0) * params.dt;
-                    if rand::random::<f64>() < p {
-                        HealthState::Infected
-                    } else {
-                        HealthState::Susceptible
-                    }
-                }
-                HealthState::Infected => {
-                    if rand::random::<f64>() < params.gamma * params.dt {
-                        HealthState::Recovered
-                    } else {
-                        HealthState::Infected
+impl rand::RngCore for CellRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        splitmix64(self.state)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// `next_state` of the cell at (x, y) for the per-cell-seeded steppers.
+/// Per-cell layers and mobility are not supported.
+fn seeded_cell_update(grid: &Grid, x: usize, y: usize, params: &SirParams, seed: u64, day: usize) -> HealthState {
+    let idx = grid.get_index(x, y);
+    let layers = CellLayers::default();
+    let ctx = CellContext { grid, layers: &layers, params, x, y, idx };
+    next_state(grid.read(idx), &ctx, &mut CellRng::new(seed, day, idx))
+}
+
+/// Sequential reference for `step_grid_parallel`: every cell draws from its
+/// own RNG seeded by (`seed`, `day`, cell index). Returns the number of new infections.
+pub fn step_grid_seeded(grid: &mut Grid, params: &SirParams, seed: u64, day: usize) -> usize {
+    let current = grid.clone();
+    let mut new_infections = 0;
+    let mut idx = 0;
+    for y in 0..current.grid_y {
+        for x in 0..current.grid_x {
+            let updated = seeded_cell_update(&current, x, y, params, seed, day);
+            if updated == HealthState::Infected && current.read(idx) != HealthState::Infected {
+                new_infections += 1;
+            }
+            grid.write(idx, updated);
+            idx += 1;
+        }
+    }
+    new_infections
+}
+
+/// Advance the grid one step, computing rows in parallel with rayon.
+/// Draws are seeded per cell as in `step_grid_seeded`, so the result is
+/// identical to it whatever the thread count. Pass the day number so each
+/// day gets fresh draws. Returns the number of new infections.
+pub fn step_grid_parallel(grid: &mut Grid, params: &SirParams, seed: u64, day: usize) -> usize {
+    use rayon::prelude::*;
+
+    let current: &Grid = grid;
+    let rows: Vec<(Vec<HealthState>, usize)> = (0..current.grid_y)
+        .into_par_iter()
+        .map(|y| {
+            let row_start = y * current.grid_x;
+            let mut new_infections = 0;
+            let row = (0..current.grid_x)
+                .map(|x| {
+                    let updated = seeded_cell_update(current, x, y, params, seed, day);
+                    if updated == HealthState::Infected && current.read(row_start + x) != HealthState::Infected {
+                        new_infections += 1;
                     }
+                    updated
+                })
+                .collect();
+            (row, new_infections)
+        })
+        .collect();
+
+    // Rows come back in order, so their cells are consecutive linear indices
+    for (idx, state) in rows.iter().flat_map(|(row, _)| row).enumerate() {
+        grid.write(idx, *state);
+    }
+    rows.iter().map(|(_, count)| count).sum()
+}
+
+/// Furthest (Chebyshev) offset at which an infected cell affects another.
+fn interaction_reach(params: &SirParams) -> usize {
+    match &params.kernel {
+        Some(kernel) => kernel.size() / 2,
+        None => params.radius.max(1),
+    }
+}
+
+/// Bounding box of the cells that can change on the next step: every infected
+/// cell and everything within interaction reach of one. Ends are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRegion {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl DirtyRegion {
+    /// The whole grid.
+    pub fn full(grid: &Grid) -> Self {
+        DirtyRegion { x0: 0, y0: 0, x1: grid.grid_x, y1: grid.grid_y }
+    }
+
+    /// Region around the infected cells of `grid`, found by scanning every cell.
+    /// `None` when nothing is infected, since then nothing can change.
+    pub fn around_infected(grid: &Grid, params: &SirParams) -> Option<Self> {
+        let mut bounds = None;
+        for ((x, y), state) in grid.iter_cells() {
+            if state == HealthState::Infected {
+                bounds = Some(Self::extend(bounds, x, y));
+            }
+        }
+        bounds.map(|bounds| Self::grow(grid, bounds, interaction_reach(params)))
+    }
+
+    /// Number of cells in the region.
+    pub fn area(&self) -> usize {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+
+    /// Inclusive (min_x, min_y, max_x, max_y) `bounds` extended to cover (x, y).
+    fn extend(bounds: Option<(usize, usize, usize, usize)>, x: usize, y: usize) -> (usize, usize, usize, usize) {
+        match bounds {
+            Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+            None => (x, y, x, y),
+        }
+    }
+
+    /// Inclusive `bounds` grown by `reach` on every side, clipped to the grid.
+    fn grow(grid: &Grid, (x0, y0, x1, y1): (usize, usize, usize, usize), reach: usize) -> Self {
+        DirtyRegion {
+            x0: x0.saturating_sub(reach),
+            y0: y0.saturating_sub(reach),
+            x1: (x1 + reach + 1).min(grid.grid_x),
+            y1: (y1 + reach + 1).min(grid.grid_y),
+        }
+    }
+}
+
+/// `step_grid_seeded` that only visits the cells in `region`, then shrinks or
+/// grows `region` to follow the infected cells. Cells outside it have no
+/// infected neighbors and so can't change, which makes each step cost the
+/// size of the epidemic front rather than of the grid. Start from
+/// `DirtyRegion::around_infected`; the result is identical to `step_grid_seeded`.
+/// Vaccination and waning can change any cell, so with either enabled every
+/// cell is stepped. Returns the number of new infections.
+pub fn step_grid_dirty(grid: &mut Grid, params: &SirParams, seed: u64, day: usize, region: &mut Option<DirtyRegion>) -> usize {
+    if params.vaccination_rate > 0.0 || params.waning_rate > 0.0 {
+        let new_infections = step_grid_seeded(grid, params, seed, day);
+        *region = Some(DirtyRegion::full(grid));
+        return new_infections;
+    }
+    let Some(DirtyRegion { x0, y0, x1, y1 }) = *region else {
+        return 0;
+    };
+
+    let mut updates = Vec::with_capacity((x1 - x0) * (y1 - y0));
+    let mut new_infections = 0;
+    let mut bounds = None;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let idx = grid.get_index(x, y);
+            let updated = seeded_cell_update(grid, x, y, params, seed, day);
+            if updated == HealthState::Infected {
+                if grid.read(idx) != HealthState::Infected {
+                    new_infections += 1;
                 }
-                HealthState::Recovered => HealthState::Recovered,
-            };
+                bounds = Some(DirtyRegion::extend(bounds, x, y));
+            }
+            updates.push((idx, updated));
+        }
+    }
+    for (idx, state) in updates {
+        grid.write(idx, state);
+    }
+    *region = bounds.map(|bounds| DirtyRegion::grow(grid, bounds, interaction_reach(params)));
+    new_infections
+}
 
-            output.write(idx, new_state);
+/// Step the cells of `tile` into `output` with `next_state`, reading neighbors
+/// from the whole grid so tile edges don't cut neighborhoods short. Each cell
+/// draws from its own RNG seeded by (`seed`, `day`, cell index), exactly as in
+/// `step_grid_seeded`. Only cells inside both the tile's grid and `output` are
+/// processed, so a tile reaching past the edge is clipped rather than causing a panic.
+pub fn step_tile(tile: &Tile, params: &SirParams, seed: u64, day: usize, output: &mut Grid) {
+    for (idx, state) in tile_updates(tile, params, seed, day, output.grid_x, output.grid_y) {
+        output.write(idx, state);
+    }
+}
+
+/// (linear index, next state) of the cells `step_tile` would write into a
+/// `width` x `height` output grid.
+fn tile_updates(tile: &Tile, params: &SirParams, seed: u64, day: usize, width: usize, height: usize) -> Vec<(usize, HealthState)> {
+    let tile_width = tile.tile_x.min(width.saturating_sub(tile.origin_x));
+    let tile_height = tile.tile_y.min(height.saturating_sub(tile.origin_y));
+    let mut updates = Vec::with_capacity(tile_width * tile_height);
+    for y in 0..tile_height {
+        for x in 0..tile_width {
+            if tile.get_state(x, y).is_none() {
+                continue;
+            }
+            let (gx, gy) = (tile.origin_x + x, tile.origin_y + y);
+            updates.push((gy * width + gx, seeded_cell_update(tile.grid, gx, gy, params, seed, day)));
         }
     }
+    updates
 }
 
 /*
 pub fn step_grid_tiled(grid: &Grid, params: &SirParams, tile_width: usize, tile_height: usize) -> Grid {
     let mut next = Grid::init(grid.grid_x, grid.grid_y, &SirParams { beta: 0.0, gamma: 0.0, dt: 1.0, i_ratio: 0.0, s_ratio: 1.0 });
-    let tiles = tile_grid(grid, tile_width, tile_height);
+    let tiles = tile_grid(grid, tile_width, tile_height).unwrap();
     for tile in &tiles {
         step_tile(tile, params, &mut next);
     }
     next
 }*/
 
-pub fn step_grid_tiled(grid: &Grid, params: &SirParams, tile_width: usize, tile_height: usize) -> Grid {
-    // Initialize the next grid
-    let mut next = Grid::init(grid.grid_x, grid.grid_y, &SirParams { beta: 0.0, gamma: 0.0, dt: 1.0, i_ratio: 0.0, s_ratio: 1.0 });
+/// Advance the grid one step, processing `tile_width` x `tile_height` tiles in
+/// parallel. Draws are seeded per cell, so the result matches `step_grid_seeded`
+/// for the same `seed` and `day` whatever the tile size.
+/// Fails if a tile dimension is 0 (see `tile_grid`).
+pub fn step_grid_tiled(grid: &Grid, params: &SirParams, seed: u64, day: usize, tile_width: usize, tile_height: usize) -> Result<Grid, SirError> {
+    use rayon::prelude::*;
 
     // Split the grid into tiles
-    let tiles = tile_grid(grid, tile_width, tile_height);
+    let tiles = tile_grid(grid, tile_width, tile_height)?;
 
-    // Use a thread-safe wrapper for the next grid
-    use rayon::prelude::*;
-    use std::sync::Mutex;
-    let next_mutex = Mutex::new(&mut next);
+    // Tiles only read the current grid, so they compute in parallel; packed
+    // cells share bytes across tile edges, so the writes happen afterwards
+    let updates: Vec<Vec<(usize, HealthState)>> = tiles
+        .par_iter()
+        .map(|tile| tile_updates(tile, params, seed, day, grid.grid_x, grid.grid_y))
+        .collect();
+    let mut next = grid.clone();
+    for (idx, state) in updates.into_iter().flatten() {
+        next.write(idx, state);
+    }
 
-    // Process each tile in parallel
-    tiles.par_iter().for_each(|tile| {
-        let mut next_guard = next_mutex.lock().unwrap(); // Lock the grid for writing
-        step_tile(tile, params, &mut next_guard);
-    });
+    Ok(next)
+}
 
-    next
+/// `step_grid_tiled` with tiles sized by `optimal_tile_size`.
+pub fn step_grid_tiled_auto(grid: &Grid, params: &SirParams, seed: u64, day: usize) -> Result<Grid, SirError> {
+    let (tile_width, tile_height) = optimal_tile_size(grid.grid_x, grid.grid_y);
+    step_grid_tiled(grid, params, seed, day, tile_width, tile_height)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::utils::maths::SirParams;
+/// Day -> beta override, e.g. a lockdown or seasonal forcing.
+pub type BetaSchedule = Arc<dyn Fn(usize) -> f64 + Send + Sync>;
 
-    fn dummy_params(i_ratio: f64, beta: f64, gamma: f64, dt: f64) -> SirParams {
-        SirParams { beta, gamma, dt, i_ratio, s_ratio: 1.0 }
-    }
+/// Where `run_until` sends periodic `Progress` updates; see `Simulation::with_progress`.
+pub type ProgressReporter = Arc<dyn Fn(&Progress) + Send + Sync>;
 
-    #[test]
-    // Counts 4 infected neighbors around a center cell
-    fn test_simulation_count_infected_neighbors_case1() {
-        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
-        // Setup infected neighbors
-        grid.write(grid.get_index(0, 0), HealthState::Infected);
-        grid.write(grid.get_index(1, 0), HealthState::Infected);
-        grid.write(grid.get_index(2, 1), HealthState::Infected);
-        grid.write(grid.get_index(2, 2), HealthState::Infected);
+/// Snapshot of a running `run_until` call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub day: usize,
+    pub infected: usize,
+    /// Wall-clock time since the run started.
+    pub elapsed: Duration,
+    /// Time left if the run goes all the way to `max_days` at the pace so far.
+    pub eta: Duration,
+}
 
-        let count = count_infected_neighbors(&grid, 1, 1);
-        assert_eq!(count, 4);
+/// Reporter that logs each update at info level.
+pub fn log_progress(progress: &Progress) {
+    log::info!(
+        "day {}: {} infected, {:.1?} elapsed, ~{:.1?} remaining",
+        progress.day, progress.infected, progress.elapsed, progress.eta
+    );
+}
+
+/// Day limit for `Simulation::run` unless set with `with_max_days`.
+pub const DEFAULT_MAX_DAYS: usize = 1000;
+
+/// Owns a grid, its parameters and RNG, and advances them one day at a time.
+pub struct Simulation {
+    pub grid: Grid,
+    pub params: SirParams,
+    /// Counts for day 0 and after every step.
+    pub history: History,
+    day: usize,
+    rng: StdRng,
+    layers: CellLayers,
+    beta_schedule: Option<BetaSchedule>,
+    deterministic_threshold: Option<f64>,
+    progress: Option<(usize, ProgressReporter)>,
+    max_days: usize,
+    seed: Option<u64>,
+    /// Day of `history`'s first entry; nonzero after `from_grid`.
+    history_start: usize,
+}
+
+/// Settings a run was made with, saved next to its grid by `save_run`.
+/// `seed` is `None` for runs seeded from OS entropy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub crate_version: String,
+    pub seed: Option<u64>,
+    pub width: usize,
+    pub height: usize,
+    pub day: usize,
+    pub params: SirParams,
+}
+
+/// On-disk layout of `save_run`: metadata plus one dense state byte per cell.
+#[derive(Serialize, Deserialize)]
+struct RunFile {
+    metadata: RunMetadata,
+    cells: Vec<u8>,
+}
+
+/// Everything needed to resume a `Simulation` exactly where it was captured.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub grid: Grid,
+    pub history: History,
+    pub day: usize,
+    pub params: SirParams,
+    rng: StdRng,
+    layers: CellLayers,
+    beta_schedule: Option<BetaSchedule>,
+    deterministic_threshold: Option<f64>,
+    progress: Option<(usize, ProgressReporter)>,
+    max_days: usize,
+    seed: Option<u64>,
+    history_start: usize,
+}
+
+impl Simulation {
+    /// New simulation with an RNG seeded from OS entropy.
+    pub fn new(grid: Grid, params: SirParams) -> Self {
+        let history = History::from_initial(count_states_fast(&grid));
+        Simulation {
+            grid,
+            params,
+            history,
+            day: 0,
+            rng: StdRng::from_entropy(),
+            layers: CellLayers::default(),
+            beta_schedule: None,
+            deterministic_threshold: None,
+            progress: None,
+            max_days: DEFAULT_MAX_DAYS,
+            seed: None,
+            history_start: 0,
+        }
     }
 
-    #[test]
-    // Cell surrounded by infected neighbors should almost always get infected
-    fn test_simulation_process_susceptible_case1() {
-        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 1.0, 0.0, 1.0));
-        // all infected
-        for y in 0..3 {
-            for x in 0..3 {
-                grid.write(grid.get_index(x, y), HealthState::Infected);
-            }
+    /// Continue from `grid` as it stands on day `start_day`, e.g. after changing
+    /// parameters mid-run. The RNG is seeded with `seed`, and beta schedules and
+    /// `infection_day_map` stamps count days from `start_day`. `history` restarts
+    /// at `start_day`: entry 0 holds `grid`'s counts, and its incidence and
+    /// transmissions are indexed from there too. To keep the original RNG stream
+    /// and history instead, use `snapshot` / `restore` and then edit `params`.
+    /// Parameters are checked as in `try_new`.
+    pub fn from_grid(grid: Grid, params: SirParams, seed: u64, start_day: usize) -> Result<Self, SirError> {
+        let mut sim = Simulation::try_new(grid, params)?.with_seed(seed);
+        sim.day = start_day;
+        sim.history_start = start_day;
+        sim.layers.steps = start_day as u32;
+        Ok(sim)
+    }
+
+    /// Like `new`, but rejects parameters that fail `SirParams::validate` or a
+    /// `beta_field` that doesn't hold one entry per cell.
+    pub fn try_new(grid: Grid, params: SirParams) -> Result<Self, SirError> {
+        params.validate()?;
+        if let Some(field) = &params.beta_field
+            && field.len() != grid.grid_x * grid.grid_y
+        {
+            return Err(GridError::LengthMismatch { expected: grid.grid_x * grid.grid_y, found: field.len() }.into());
         }
-        let result = process_susceptible(&grid, 1, 1, &dummy_params(0.0, 1.0, 0.0, 1.0));
-        assert_eq!(result, HealthState::Infected);
+        Ok(Simulation::new(grid, params))
     }
 
-    #[test]
-    // With beta = 0.0, cell should not get infected even if surrounded
-    fn test_simulation_process_susceptible_case2() {
-        let grid = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
-        let result = process_susceptible(&grid, 1, 1, &dummy_params(0.0, 0.0, 0.0, 1.0));
-        assert_eq!(result, HealthState::Susceptible);
+    /// Reseed the RNG so the run is reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = Some(seed);
+        self
     }
 
-    #[test]
-    // Infected cell should always recover when gamma = 1.0
-    fn test_simulation_process_infected_case1() {
-        let result = process_infected(&dummy_params(0.0, 0.0, 1.0, 1.0));
-        assert_eq!(result, HealthState::Recovered);
+    /// Give every cell an age bracket drawn from `distribution` (relative weights
+    /// of child, adult, elderly). Recovery is then scaled by `params.age_gamma`.
+    pub fn with_age_structure(mut self, distribution: [f64; 3]) -> Self {
+        self.layers.ages = CellLayers::init_age_structured(&self.grid, distribution, &mut self.rng).ages;
+        self
     }
 
-    #[test]
-    // Infected cell should never recover when gamma = 0.0
-    fn test_simulation_process_infected_case2() {
-        let result = process_infected(&dummy_params(0.0, 0.0, 0.0, 1.0));
-        assert_eq!(result, HealthState::Infected);
+    /// Record the day each cell is first infected; see `infection_day_map`.
+    pub fn with_infection_day_map(mut self) -> Self {
+        self.layers.track_infection_days(&self.grid);
+        self
     }
 
-    #[test]
-    // After one step, a susceptible center cell should become infected
-    fn test_simulation_step_grid_case1() {
-        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 1.0, 0.0, 1.0));
-        // all infected
-        for y in 0..3 {
-            for x in 0..3 {
-                grid.write(grid.get_index(x, y), HealthState::Infected);
-            }
+    /// Attribute every new infection to the infected neighbors that caused it,
+    /// in proportion to their share of the infection pressure, and keep the
+    /// totals per infection day in `history.transmissions` (see `effective_r`).
+    /// Enable before stepping. Deterministic runs don't attribute infections.
+    pub fn with_transmission_tracking(mut self) -> Self {
+        self.layers.track_transmissions(&self.grid);
+        self
+    }
+
+    /// Day each cell was first infected (`NEVER_INFECTED` if it hasn't been),
+    /// indexed like the grid cells. Empty unless `with_infection_day_map` was used.
+    pub fn infection_day_map(&self) -> &[u32] {
+        &self.layers.infection_days
+    }
+
+    /// Step with `step_grid_deterministic` at `threshold` instead of drawing from the RNG.
+    pub fn with_deterministic_threshold(mut self, threshold: f64) -> Self {
+        self.deterministic_threshold = Some(threshold);
+        self
+    }
+
+    /// Replace `params.beta` each step with `schedule(day)`.
+    /// Without a schedule the constant `params.beta` is used.
+    pub fn with_beta_schedule(mut self, schedule: impl Fn(usize) -> f64 + Send + Sync + 'static) -> Self {
+        self.beta_schedule = Some(Arc::new(schedule));
+        self
+    }
+
+    /// Call `reporter` from `run_until` (and `run_with`) after every `every_days`
+    /// steps. Use `log_progress` to log instead of handling updates yourself.
+    pub fn with_progress(mut self, every_days: usize, reporter: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        assert!(every_days > 0, "Progress period must be at least one day");
+        self.progress = Some((every_days, Arc::new(reporter)));
+        self
+    }
+
+    /// Day limit for `run`.
+    pub fn with_max_days(mut self, max_days: usize) -> Self {
+        self.max_days = max_days;
+        self
+    }
+
+    /// Capture the grid, day count, parameters and RNG state.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            grid: self.grid.clone(),
+            history: self.history.clone(),
+            day: self.day,
+            params: self.params.clone(),
+            rng: self.rng.clone(),
+            layers: self.layers.clone(),
+            beta_schedule: self.beta_schedule.clone(),
+            deterministic_threshold: self.deterministic_threshold,
+            progress: self.progress.clone(),
+            max_days: self.max_days,
+            seed: self.seed,
+            history_start: self.history_start,
+        }
+    }
+
+    /// Resume from a snapshot; the subsequent trajectory matches the original run.
+    pub fn restore(snapshot: Snapshot) -> Simulation {
+        Simulation {
+            grid: snapshot.grid,
+            params: snapshot.params,
+            history: snapshot.history,
+            day: snapshot.day,
+            rng: snapshot.rng,
+            layers: snapshot.layers,
+            beta_schedule: snapshot.beta_schedule,
+            deterministic_threshold: snapshot.deterministic_threshold,
+            progress: snapshot.progress,
+            max_days: snapshot.max_days,
+            seed: snapshot.seed,
+            history_start: snapshot.history_start,
+        }
+    }
+
+    /// Step the grid and the day counter without touching `history`'s daily
+    /// entries. Returns the step's transitions.
+    fn step_grid_only(&mut self) -> TransitionCounts {
+        if let Some(schedule) = &self.beta_schedule {
+            self.params.beta = schedule(self.day);
+        }
+        let transitions = match self.deterministic_threshold {
+            Some(threshold) => step_grid_deterministic_transitions(&mut self.grid, &self.params, threshold),
+            None => step_grid_layered_transitions(&mut self.grid, &mut self.layers, &self.params, &mut self.rng),
+        };
+        self.day += 1;
+        if !self.layers.infected_on.is_empty() {
+            let start = self.history_start.min(self.layers.transmissions.len());
+            self.history.transmissions.clear();
+            self.history.transmissions.extend_from_slice(&self.layers.transmissions[start..]);
+        }
+        transitions
+    }
+
+    /// Advance the grid by one time step.
+    pub fn step(&mut self) {
+        let transitions = self.step_grid_only();
+        let new_infections = transitions.new_infections();
+        let stats = count_states_fast(&self.grid);
+        self.history.record_step(stats, new_infections);
+        self.history.transitions.push(transitions);
+        log::debug!(
+            "day {}: S={} I={} R={} ({} new infections)",
+            self.day, stats.susceptible, stats.infected, stats.recovered, new_infections
+        );
+    }
+
+    /// Latest counts as reported with `params.reporting_delay`; see `History::reported_stats`.
+    pub fn reported_stats(&self) -> PopulationStats {
+        let day = self.history.days.len() - 1;
+        self.history.reported_stats(day, self.params.reporting_delay).expect("history has an initial entry")
+    }
+
+    /// Number of steps taken so far (plus `start_day` for `from_grid`).
+    pub fn day(&self) -> usize {
+        self.day
+    }
+
+    /// Seed, grid size, day and parameters of this run.
+    pub fn metadata(&self) -> RunMetadata {
+        RunMetadata {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            seed: self.seed,
+            width: self.grid.grid_x,
+            height: self.grid.grid_y,
+            day: self.day,
+            params: self.params.clone(),
+        }
+    }
+
+    /// Write `metadata()` and the current grid to `path` as JSON.
+    pub fn save_run(&self, path: impl AsRef<std::path::Path>) -> Result<(), SirError> {
+        let file = RunFile { metadata: self.metadata(), cells: self.grid.to_dense() };
+        let json = serde_json::to_vec_pretty(&file).map_err(std::io::Error::from)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// Read a run written by `save_run`. Parameters come back exactly as saved
+    /// and are checked with `SirParams::validate`; pass them and the seed to
+    /// `from_grid` to carry on from the saved day.
+    pub fn load_run(path: impl AsRef<std::path::Path>) -> Result<(RunMetadata, Grid), SirError> {
+        let json = std::fs::read(path)?;
+        let file: RunFile = serde_json::from_slice(&json).map_err(SirError::InvalidFile)?;
+        file.metadata.params.validate()?;
+        let grid = Grid::from_dense(file.metadata.width, file.metadata.height, &file.cells)?;
+        Ok((file.metadata, grid))
+    }
+
+    /// Step up to `days` times without counting states, stopping early once no
+    /// infected cells remain. The grid ends up exactly where the same number of
+    /// `step` calls would leave it, but no `history` entries are recorded for
+    /// these days (`cumulative_infections` is still kept up to date).
+    /// Returns the number of steps taken.
+    pub fn advance(&mut self, days: usize) -> usize {
+        for steps in 1..=days {
+            let new_infections = self.step_grid_only().new_infections();
+            self.history.cumulative_infections += new_infections;
+            self.history.incidence.push(new_infections);
+            if !self.grid.any_infected() {
+                return steps;
+            }
+        }
+        days
+    }
+
+    /// Step up to `max_days` times, calling `on_step(day, stats, grid)` after each step.
+    /// Stops early once no infected cells remain. Returns the number of steps taken.
+    pub fn run_with(&mut self, max_days: usize, on_step: impl FnMut(usize, &PopulationStats, &Grid)) -> usize {
+        self.run_until(max_days, on_step).0
+    }
+
+    /// `run_with`, also returning why the run stopped.
+    /// Deterministic runs without a beta schedule also stop with
+    /// `TerminationReason::SteadyState` once a step leaves the grid unchanged,
+    /// since every later step would too. Stochastic runs can't freeze that way.
+    pub fn run_until(&mut self, max_days: usize, mut on_step: impl FnMut(usize, &PopulationStats, &Grid)) -> (usize, TerminationReason) {
+        if !self.grid.any_infected() || (!self.params.can_transmit() && self.beta_schedule.is_none()) {
+            log::warn!("no epidemic is possible: nothing is infected, or beta, dt or the kernel rule out transmission");
+        }
+        let can_freeze = self.deterministic_threshold.is_some() && self.beta_schedule.is_none();
+        let started = Instant::now();
+        for steps in 1..=max_days {
+            let previous = can_freeze.then(|| self.grid.clone());
+            self.step();
+            let stats = *self.history.days.last().expect("history records every step");
+            on_step(self.day, &stats, &self.grid);
+            if let Some((every_days, reporter)) = &self.progress
+                && steps.is_multiple_of(*every_days)
+            {
+                let elapsed = started.elapsed();
+                let eta = elapsed.mul_f64((max_days - steps) as f64 / steps as f64);
+                reporter(&Progress { day: self.day, infected: stats.infected, elapsed, eta });
+            }
+            if is_finished(&stats) {
+                log::info!("infection died out on day {}", self.day);
+                return (steps, TerminationReason::Extinct);
+            }
+            if previous.as_ref() == Some(&self.grid) {
+                log::info!("grid reached a steady state on day {}", self.day);
+                return (steps, TerminationReason::SteadyState);
+            }
+        }
+        (max_days, TerminationReason::MaxDays)
+    }
+
+    /// `run_until` with the simulation's own day limit (`DEFAULT_MAX_DAYS`
+    /// unless set with `with_max_days`) and no per-step callback.
+    pub fn run(&mut self) -> (usize, TerminationReason) {
+        self.run_until(self.max_days, |_, _, _| {})
+    }
+
+    /// `run_until`, returning a copy of the grid as it starts and after every
+    /// `every` steps, plus the final grid if the run ends between captures.
+    /// Ready to hand to an animation exporter.
+    pub fn run_collecting(&mut self, max_days: usize, every: usize) -> Vec<Grid> {
+        assert!(every > 0, "Capture period must be at least one day");
+        let mut frames = vec![self.grid.clone()];
+        let mut steps: usize = 0;
+        self.run_until(max_days, |_, _, grid| {
+            steps += 1;
+            if steps.is_multiple_of(every) {
+                frames.push(grid.clone());
+            }
+        });
+        if !steps.is_multiple_of(every) {
+            frames.push(self.grid.clone());
+        }
+        frames
+    }
+
+    /// `run_until`, writing the starting grid and the grid after every step to
+    /// `sink` instead of keeping them. After a write fails the run carries on
+    /// without writing, and the error is returned at the end.
+    pub fn run_into(&mut self, max_days: usize, sink: &mut impl FrameSink) -> Result<(usize, TerminationReason), SirError> {
+        sink.write_frame(&self.grid)?;
+        let mut written = Ok(());
+        let outcome = self.run_until(max_days, |_, _, grid| {
+            if written.is_ok() {
+                written = sink.write_frame(grid);
+            }
+        });
+        written?;
+        Ok(outcome)
+    }
+
+    /// Run on a background thread, sending each day's counts as it completes.
+    /// The channel closes when the run ends; dropping the receiver stops the run.
+    pub fn run_streaming(mut self, max_days: usize) -> Receiver<PopulationStats> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for _ in 0..max_days {
+                self.step();
+                let stats = *self.history.days.last().expect("history records every step");
+                if tx.send(stats).is_err() || is_finished(&stats) {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+}
+
+/// The one-stop way to assemble a `Simulation`. Every setting has a default,
+/// so only `width` and `height` are needed:
+/// - `params`: `SirParams::default()`
+/// - `seed`: none, so the initial grid and the run draw from OS entropy
+/// - `neighborhood`: whatever `params` says (Moore by default)
+/// - `initial_infected`: none, so cells start infected at random with `params.i_ratio`
+/// - `max_days`: `DEFAULT_MAX_DAYS`
+///
+/// Everything is checked in `build`. The grid has fixed (non-wrapping) edges.
+#[derive(Debug, Clone, Default)]
+pub struct SimulationBuilder {
+    width: usize,
+    height: usize,
+    params: SirParams,
+    seed: Option<u64>,
+    initial_infected: Option<Vec<(usize, usize)>>,
+    max_days: Option<usize>,
+}
+
+impl SimulationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: usize) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Replaces all parameters, including any neighborhood set earlier.
+    pub fn params(mut self, params: SirParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Seed for both the initial grid and the run, making them reproducible.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.params.neighborhood = neighborhood;
+        self
+    }
+
+    /// Start with exactly these cells infected instead of a random `i_ratio` share.
+    pub fn initial_infected(mut self, cells: &[(usize, usize)]) -> Self {
+        self.initial_infected = Some(cells.to_vec());
+        self
+    }
+
+    pub fn max_days(mut self, max_days: usize) -> Self {
+        self.max_days = Some(max_days);
+        self
+    }
+
+    /// Check every setting and create the grid and simulation.
+    /// Fails on zero dimensions, invalid parameters (see `SirParams::validate`),
+    /// a grid too large to allocate, or an initial infection outside the grid.
+    pub fn build(self) -> Result<Simulation, SirError> {
+        if self.width == 0 {
+            return Err(SirError::InvalidParam { name: "width", value: 0.0 });
+        }
+        if self.height == 0 {
+            return Err(SirError::InvalidParam { name: "height", value: 0.0 });
+        }
+        self.params.validate()?;
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let grid = match &self.initial_infected {
+            Some(cells) => {
+                let mut grid = Grid::try_init_with_rng(self.width, self.height, &SirParams { i_ratio: 0.0, ..self.params.clone() }, &mut rng)?;
+                for &(x, y) in cells {
+                    if x >= self.width || y >= self.height {
+                        return Err(SirError::OutOfBounds { x, y, width: self.width, height: self.height });
+                    }
+                    grid.write(grid.get_index(x, y), HealthState::Infected);
+                }
+                grid
+            }
+            None => Grid::try_init_with_rng(self.width, self.height, &self.params, &mut rng)?,
+        };
+        let sim = Simulation::try_new(grid, self.params)?.with_max_days(self.max_days.unwrap_or(DEFAULT_MAX_DAYS));
+        Ok(match self.seed {
+            Some(seed) => sim.with_seed(seed),
+            None => sim,
+        })
+    }
+}
+
+/// Per-day mean and standard deviation of one state's count across replicates.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SeriesStats {
+    pub mean: Vec<f64>,
+    pub std_dev: Vec<f64>,
+}
+
+impl SeriesStats {
+    fn from_runs(runs: &[Vec<usize>]) -> Self {
+        let n = runs.len() as f64;
+        let days = runs.first().map_or(0, Vec::len);
+        let mut stats = SeriesStats::default();
+        for day in 0..days {
+            let mean = runs.iter().map(|run| run[day] as f64).sum::<f64>() / n;
+            let variance = runs.iter().map(|run| (run[day] as f64 - mean).powi(2)).sum::<f64>() / n;
+            stats.mean.push(mean);
+            stats.std_dev.push(variance.sqrt());
+        }
+        stats
+    }
+}
+
+/// Rough cost of a run, from `estimate_run_cost`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunEstimate {
+    /// Footprint of the grid itself.
+    pub memory: GridMemory,
+    /// Heap bytes held while stepping: the grid plus the next-state copy.
+    pub peak_heap_bytes: usize,
+    /// Extrapolated wall time of one `step_grid` call.
+    pub per_day: Duration,
+    /// `per_day` times the expected number of days.
+    pub total: Duration,
+}
+
+/// Side of the grid `estimate_run_cost` times, and how many steps it takes.
+const ESTIMATE_GRID_SIDE: usize = 128;
+const ESTIMATE_STEPS: u32 = 5;
+
+/// Estimate the memory and time of stepping a `width` x `height` grid for
+/// `expected_days` days with default parameters, without allocating it.
+/// The time comes from stepping a small grid on this machine and scaling by
+/// cell count, so it ignores cache effects and is only a rough guide.
+pub fn estimate_run_cost(width: usize, height: usize, expected_days: usize) -> Result<RunEstimate, SirError> {
+    let memory = Grid::memory_for(width, height)?;
+    let params = SirParams::default();
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut sample = Grid::try_init_with_rng(ESTIMATE_GRID_SIDE, ESTIMATE_GRID_SIDE, &params, &mut rng)?;
+    let started = Instant::now();
+    for _ in 0..ESTIMATE_STEPS {
+        step_grid_with_rng(&mut sample, &params, &mut rng);
+    }
+    let per_cell = started.elapsed().as_secs_f64() / (ESTIMATE_STEPS as f64 * (ESTIMATE_GRID_SIDE * ESTIMATE_GRID_SIDE) as f64);
+    let per_day = Duration::from_secs_f64(per_cell * (width * height) as f64);
+    Ok(RunEstimate {
+        memory,
+        peak_heap_bytes: 2 * memory.heap_bytes,
+        per_day,
+        total: per_day.mul_f64(expected_days as f64),
+    })
+}
+
+/// Outcome of `run_simulation`.
+#[derive(Debug, Clone)]
+pub struct SimulationResult {
+    pub grid: Grid,
+    /// Counts for day 0 and every day simulated, so `days + 1` entries.
+    pub history: History,
+    /// Steps taken.
+    pub days: usize,
+    pub reason: TerminationReason,
+}
+
+/// Run a seeded simulation on a `width` x `height` grid until the infection
+/// dies out, the grid stops changing or `max_days` steps have been taken.
+/// Panics on settings `SimulationBuilder::build` rejects; use the builder
+/// directly to handle those as errors.
+pub fn run_simulation(params: SirParams, width: usize, height: usize, seed: u64, max_days: usize) -> SimulationResult {
+    let mut sim = SimulationBuilder::new()
+        .width(width)
+        .height(height)
+        .params(params)
+        .seed(seed)
+        .max_days(max_days)
+        .build()
+        .unwrap_or_else(|err| panic!("Invalid simulation settings: {}", err));
+    let (days, reason) = sim.run();
+    SimulationResult { grid: sim.grid, history: sim.history, days, reason }
+}
+
+/// Ensemble of replicate runs; entry 0 of each series is the initial state.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnsembleStats {
+    pub susceptible: SeriesStats,
+    pub infected: SeriesStats,
+    pub recovered: SeriesStats,
+}
+
+/// Run `n_reps` seeded simulations of `days` steps in parallel and summarize
+/// their S/I/R counts per day. Replicate `k` uses seed `base_seed + k` for both
+/// the initial grid and the run. Runs are not stopped early, so every series has
+/// `days + 1` entries.
+pub fn run_replicates(params: &SirParams, width: usize, height: usize, days: usize, n_reps: usize, base_seed: u64) -> EnsembleStats {
+    use rayon::prelude::*;
+
+    let histories: Vec<History> = (0..n_reps as u64)
+        .into_par_iter()
+        .map(|rep| {
+            let seed = base_seed.wrapping_add(rep);
+            let grid = Grid::init_seeded(width, height, params, seed);
+            let mut sim = Simulation::new(grid, params.clone()).with_seed(seed);
+            for _ in 0..days {
+                sim.step();
+            }
+            sim.history
+        })
+        .collect();
+
+    let series = |state: fn(&PopulationStats) -> usize| {
+        let runs: Vec<Vec<usize>> = histories.iter().map(|history| history.days.iter().map(state).collect()).collect();
+        SeriesStats::from_runs(&runs)
+    };
+    EnsembleStats {
+        susceptible: series(|stats| stats.susceptible),
+        infected: series(|stats| stats.infected),
+        recovered: series(|stats| stats.recovered),
+    }
+}
+
+/// Seeded agent-based runs averaged by `compare_ode_to_agent`.
+const ODE_COMPARISON_REPLICATES: usize = 5;
+
+/// Root-mean-square difference between the mean-field (`OdeSir`) infected
+/// fraction and the agent-based one, averaged over seeded replicates on a
+/// `grid_size` x `grid_size` grid, across days 0..=`days`.
+/// Small values mean the lattice behaves like a well-mixed population.
+pub fn compare_ode_to_agent(params: &SirParams, grid_size: usize, days: usize) -> f64 {
+    let ensemble = run_replicates(params, grid_size, grid_size, days, ODE_COMPARISON_REPLICATES, 0);
+    let population = (grid_size * grid_size) as f64;
+    let agent_curve = ensemble.infected.mean.iter().map(|infected| infected / population);
+
+    // Start the ODE from the replicates' realized initial fraction, not the target i_ratio
+    let ode_curve = OdeSir::new(ensemble.infected.mean[0] / population).run(params, days);
+    let squared_error: f64 = ode_curve
+        .iter()
+        .zip(agent_curve)
+        .map(|(ode, agent)| (ode.i - agent).powi(2))
+        .sum();
+    (squared_error / (days + 1) as f64).sqrt()
+}
+
+/// Longest run `sweep` simulates waiting for the infection to die out.
+const SWEEP_MAX_DAYS: usize = 1000;
+
+/// Outcome of one (beta, gamma) combination in a `sweep`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepResult {
+    pub beta: f64,
+    pub gamma: f64,
+    /// Largest infected count on any day.
+    pub peak_infected: usize,
+    /// Infections over the run as a share of the inhabited population.
+    pub attack_rate: f64,
+    /// Steps until the infection died out, or `SWEEP_MAX_DAYS` if it never did.
+    pub duration: usize,
+}
+
+/// Run one seeded simulation per (beta, gamma) combination in parallel, other
+/// parameters at their defaults. Every combination starts from the same grid
+/// and RNG seed, so differences come from the parameters alone. Results are
+/// ordered by beta, then gamma.
+pub fn sweep(beta_range: &[f64], gamma_range: &[f64], width: usize, height: usize, seed: u64) -> Vec<SweepResult> {
+    use rayon::prelude::*;
+
+    let combinations: Vec<(f64, f64)> = beta_range
+        .iter()
+        .flat_map(|&beta| gamma_range.iter().map(move |&gamma| (beta, gamma)))
+        .collect();
+    combinations
+        .into_par_iter()
+        .map(|(beta, gamma)| {
+            let params = SirParams { beta, gamma, ..SirParams::default() };
+            let grid = Grid::init_seeded(width, height, &params, seed);
+            let mut sim = Simulation::new(grid, params).with_seed(seed);
+            let (duration, _) = sim.run_until(SWEEP_MAX_DAYS, |_, _, _| {});
+            let initial = sim.history.days[0];
+            let population = initial.total();
+            SweepResult {
+                beta,
+                gamma,
+                peak_infected: sim.history.days.iter().map(|stats| stats.infected).max().unwrap_or(0),
+                attack_rate: sim.history.cumulative_infections as f64 / population.max(1) as f64,
+                duration,
+            }
+        })
+        .collect()
+}
+
+/// Upper end of the percolation search: with Moore neighbors and dt = 1,
+/// beta = 8 infects any susceptible next to an infected cell with certainty.
+const PERCOLATION_MAX_BETA: f64 = 8.0;
+
+/// Whether an outbreak seeded along the left edge of a `width` x `height`
+/// grid ever reaches the right edge. Other parameters are the defaults.
+fn percolates(width: usize, height: usize, beta: f64, gamma: f64, seed: u64) -> bool {
+    let params = SirParams { beta, gamma, ..SirParams::default() };
+    let seeds: Vec<(usize, usize)> = (0..height).map(|y| (0, y)).collect();
+    let grid = Grid::init_with_seeds(width, height, &params, &seeds);
+    let reached_far_edge =
+        |grid: &Grid| (0..height).any(|y| grid.read(grid.get_index(width - 1, y)) != HealthState::Susceptible);
+    let mut sim = Simulation::new(grid, params).with_seed(seed);
+    // A front that is still alive after width * height days counts as stalled
+    for _ in 0..width * height {
+        if reached_far_edge(&sim.grid) || !sim.grid.any_infected() {
+            break;
+        }
+        sim.advance(1);
+    }
+    reached_far_edge(&sim.grid)
+}
+
+/// Critical beta, within `tolerance`, above which an outbreak seeded along the
+/// left edge crosses to the right edge. Bisects beta over 0..=8, running one
+/// seeded simulation per probe.
+pub fn find_percolation_threshold(width: usize, height: usize, gamma: f64, seed: u64, tolerance: f64) -> f64 {
+    assert!(tolerance > 0.0, "tolerance must be positive");
+    let (mut low, mut high) = (0.0, PERCOLATION_MAX_BETA);
+    while high - low > tolerance {
+        let mid = (low + high) / 2.0;
+        if percolates(width, height, mid, gamma, seed) {
+            high = mid;
+        } else {
+            low = mid;
         }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::maths::{AgeBracket, ContactModel, InfectionKernel, MIN_CROWDING_FACTOR, TransitionCounts, effective_r, InfectiousPeriod, InfectiousnessProfile, SirParams, count_states};
+    use crate::utils::layers::NEVER_INFECTED;
+    use crate::utils::render::{Palette, PpmSequence, write_ppm};
+
+    fn dummy_params(i_ratio: f64, beta: f64, gamma: f64, dt: f64) -> SirParams {
+        SirParams { beta, gamma, dt, i_ratio, s_ratio: 1.0, ..SirParams::default() }
+    }
+
+    #[test]
+    // Counts 4 infected neighbors around a center cell
+    fn test_simulation_count_infected_neighbors_case1() {
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        // Setup infected neighbors
+        grid.write(grid.get_index(0, 0), HealthState::Infected);
+        grid.write(grid.get_index(1, 0), HealthState::Infected);
+        grid.write(grid.get_index(2, 1), HealthState::Infected);
+        grid.write(grid.get_index(2, 2), HealthState::Infected);
+
+        let count = count_infected_neighbors(&grid, 1, 1, Neighborhood::Moore);
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    // Edge cell only fills 5 buffer slots; infected cells outside them must not count
+    fn test_simulation_count_infected_neighbors_case2() {
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        grid.write(grid.get_index(0, 0), HealthState::Infected);
+        grid.write(grid.get_index(1, 0), HealthState::Infected);
+        grid.write(grid.get_index(1, 2), HealthState::Infected);
+        grid.write(grid.get_index(0, 2), HealthState::Infected);
+        // Not adjacent to (0, 1)
+        grid.write(grid.get_index(2, 2), HealthState::Infected);
+
+        let count = count_infected_neighbors(&grid, 0, 1, Neighborhood::Moore);
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    // Cell surrounded by infected neighbors should almost always get infected
+    fn test_simulation_process_susceptible_case1() {
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 1.0, 0.0, 1.0));
+        // all infected
+        grid.fill(HealthState::Infected);
+        let result = process_susceptible(&grid, 1, 1, &dummy_params(0.0, 1.0, 0.0, 1.0), &mut rand::thread_rng());
+        assert_eq!(result, HealthState::Infected);
+    }
+
+    #[test]
+    // With beta = 0.0, cell should not get infected even if surrounded
+    fn test_simulation_process_susceptible_case2() {
+        let grid = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        let result = process_susceptible(&grid, 1, 1, &dummy_params(0.0, 0.0, 0.0, 1.0), &mut rand::thread_rng());
+        assert_eq!(result, HealthState::Susceptible);
+    }
+
+    #[test]
+    // A fully-infected surround gives beta * dt whatever the neighborhood's size
+    fn test_simulation_infection_probability_case1() {
+        let grid = Grid::init(3, 3, &dummy_params(1.0, 0.0, 0.0, 1.0));
+        let moore = dummy_params(0.0, 0.4, 0.0, 1.0);
+        let von_neumann = SirParams { neighborhood: Neighborhood::VonNeumann, ..dummy_params(0.0, 0.4, 0.0, 1.0) };
+        assert!((infection_probability(&grid, 1, 1, &moore) - 0.4).abs() < 1e-12);
+        assert!((infection_probability(&grid, 1, 1, &von_neumann) - 0.4).abs() < 1e-12);
+    }
+
+    #[test]
+    // Diagonal neighbors only transmit in the Moore neighborhood
+    fn test_simulation_infection_probability_case2() {
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        grid.write(grid.get_index(0, 0), HealthState::Infected);
+        grid.write(grid.get_index(2, 2), HealthState::Infected);
+        let moore = dummy_params(0.0, 0.8, 0.0, 1.0);
+        let von_neumann = SirParams { neighborhood: Neighborhood::VonNeumann, ..dummy_params(0.0, 0.8, 0.0, 1.0) };
+        assert!((infection_probability(&grid, 1, 1, &moore) - 0.2).abs() < 1e-12);
+        assert_eq!(infection_probability(&grid, 1, 1, &von_neumann), 0.0);
+    }
+
+    #[test]
+    // Hex cells divide by 6, and the corner a square lattice would count is not a neighbor
+    fn test_simulation_infection_probability_case3() {
+        let hex = SirParams { neighborhood: Neighborhood::Hex, ..dummy_params(0.0, 0.6, 0.0, 1.0) };
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        // Row 1 is odd, so its upper neighbors are (1, 0) and (2, 0)
+        grid.write(grid.get_index(0, 0), HealthState::Infected);
+        assert_eq!(infection_probability(&grid, 1, 1, &hex), 0.0);
+        grid.write(grid.get_index(1, 0), HealthState::Infected);
+        assert!((infection_probability(&grid, 1, 1, &hex) - 0.1).abs() < 1e-12);
+
+        let full = Grid::init(3, 3, &dummy_params(1.0, 0.0, 0.0, 1.0));
+        assert!((infection_probability(&full, 1, 1, &hex) - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    // Uniform pressure ignores distance; weighted pressure favors the orthogonal neighbor
+    fn test_simulation_infection_pressure_case1() {
+        let mut diagonal = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        diagonal.write(diagonal.get_index(0, 0), HealthState::Infected);
+        let mut orthogonal = Grid::init(3, 3, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        orthogonal.write(orthogonal.get_index(1, 0), HealthState::Infected);
+
+        let uniform = dummy_params(0.0, 1.0, 0.0, 1.0);
+        let weighted = SirParams { distance_weighted: true, ..dummy_params(0.0, 1.0, 0.0, 1.0) };
+
+        let uniform_diagonal = infection_pressure(&diagonal, 1, 1, &uniform);
+        let uniform_orthogonal = infection_pressure(&orthogonal, 1, 1, &uniform);
+        assert!((uniform_diagonal - 0.125).abs() < 1e-12);
+        assert!((uniform_orthogonal - 0.125).abs() < 1e-12);
+
+        let max_weight = 4.0 + 4.0 / 2f64.sqrt();
+        let weighted_diagonal = infection_pressure(&diagonal, 1, 1, &weighted);
+        let weighted_orthogonal = infection_pressure(&orthogonal, 1, 1, &weighted);
+        assert!((weighted_orthogonal - 1.0 / max_weight).abs() < 1e-12);
+        assert!((weighted_diagonal - 1.0 / (2f64.sqrt() * max_weight)).abs() < 1e-12);
+        assert!(weighted_diagonal < weighted_orthogonal);
+    }
+
+    #[test]
+    // Weighted pressure of a fully-infected surround is still 1
+    fn test_simulation_infection_pressure_case2() {
+        let grid = Grid::init(3, 3, &dummy_params(1.0, 0.0, 0.0, 1.0));
+        let weighted = SirParams { distance_weighted: true, ..dummy_params(0.0, 1.0, 0.0, 1.0) };
+        assert!((infection_pressure(&grid, 1, 1, &weighted) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    // A radius-2 neighborhood divides by 24, so one infected cell two steps away still counts
+    fn test_simulation_infection_pressure_case3() {
+        let mut grid = Grid::init(5, 5, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        grid.write(grid.get_index(0, 0), HealthState::Infected);
+        let radius_1 = dummy_params(0.0, 1.0, 0.0, 1.0);
+        let radius_2 = SirParams { radius: 2, ..dummy_params(0.0, 1.0, 0.0, 1.0) };
+        assert_eq!(infection_pressure(&grid, 2, 2, &radius_1), 0.0);
+        assert!((infection_pressure(&grid, 2, 2, &radius_2) - 1.0 / 24.0).abs() < 1e-12);
+    }
+
+    #[test]
+    // Infected cell should always recover when gamma = 1.0
+    fn test_simulation_process_infected_case1() {
+        let result = process_infected(&dummy_params(0.0, 0.0, 1.0, 1.0), &mut rand::thread_rng());
+        assert_eq!(result, HealthState::Recovered);
+    }
+
+    #[test]
+    // Infected cell should never recover when gamma = 0.0
+    fn test_simulation_process_infected_case2() {
+        let result = process_infected(&dummy_params(0.0, 0.0, 0.0, 1.0), &mut rand::thread_rng());
+        assert_eq!(result, HealthState::Infected);
+    }
+
+    #[test]
+    // After one step, a susceptible center cell should become infected
+    fn test_simulation_step_grid_case1() {
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 1.0, 0.0, 1.0));
+        // all infected
+        grid.fill(HealthState::Infected);
         // center susceptible
         grid.write(grid.get_index(1, 1), HealthState::Susceptible);
 
         step_grid(&mut grid, &dummy_params(0.0, 1.0, 0.0, 1.0));
         assert_eq!(grid.read(grid.get_index(1, 1)), HealthState::Infected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    // Left half has beta 0, so infection seeded at the boundary only spreads right
+    fn test_simulation_step_grid_case2() {
+        let (width, height) = (10, 4);
+        let mut grid = Grid::init(width, height, &dummy_params(0.0, 0.0, 0.0, 1.0));
+        let mut field = vec![0.0; width * height];
+        for y in 0..height {
+            for x in width / 2..width {
+                field[grid.get_index(x, y)] = 1.0;
+            }
+            // Seed the first column of the spreading half
+            grid.write(grid.get_index(width / 2, y), HealthState::Infected);
+        }
+        let params = SirParams { beta_field: Some(field), ..dummy_params(0.0, 1.0, 0.0, 1.0) };
+
+        for _ in 0..10 {
+            step_grid(&mut grid, &params);
+        }
+
+        let mut right_infected = 0;
+        for y in 0..height {
+            for x in 0..width {
+                let state = grid.read(grid.get_index(x, y));
+                if x < width / 2 {
+                    assert_eq!(state, HealthState::Susceptible);
+                } else if state == HealthState::Infected {
+                    right_infected += 1;
+                }
+            }
+        }
+        assert!(right_infected > height);
+    }
+
+    #[test]
+    // An empty cell surrounded by infected neighbors stays empty
+    fn test_simulation_step_grid_case3() {
+        let mut grid = Grid::init(3, 3, &dummy_params(1.0, 1.0, 0.0, 1.0));
+        grid.write(grid.get_index(1, 1), HealthState::Empty);
+
+        step_grid(&mut grid, &dummy_params(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(grid.read(grid.get_index(1, 1)), HealthState::Empty);
+    }
+
+    #[test]
+    // Empty neighbors drop out of the divisor: 1 infected among 1 inhabited neighbor is certain infection
+    fn test_simulation_process_susceptible_case3() {
+        let mut grid = Grid::init(3, 3, &dummy_params(0.0, 1.0, 0.0, 1.0));
+        for idx in 0..9 {
+            grid.write(idx, HealthState::Empty);
+        }
+        grid.write(grid.get_index(1, 1), HealthState::Susceptible);
+        grid.write(grid.get_index(0, 0), HealthState::Infected);
+
+        let result = process_susceptible(&grid, 1, 1, &dummy_params(0.0, 1.0, 0.0, 1.0), &mut rand::thread_rng());
+        assert_eq!(result, HealthState::Infected);
+    }
+
+    #[test]
+    // Beta drops to 0 after day 5, so the infected count freezes (gamma = 0)
+    fn test_simulation_beta_schedule_case1() {
+        let params = dummy_params(0.05, 0.5, 0.0, 1.0);
+        let grid = Grid::init(30, 30, &params);
+        let mut sim = Simulation::new(grid, params)
+            .with_beta_schedule(|day| if day < 5 { 0.5 } else { 0.0 });
+
+        for _ in 0..5 {
+            sim.step();
+        }
+        let infected_at_switch = count_states(&sim.grid).infected;
+        for _ in 0..10 {
+            sim.step();
+            assert_eq!(count_states(&sim.grid).infected, infected_at_switch);
+        }
+    }
+
+    #[test]
+    // Restoring a day-10 snapshot and rerunning reproduces the day-20 grid
+    fn test_simulation_snapshot_case1() {
+        let params = dummy_params(0.02, 0.6, 0.1, 1.0);
+        let grid = Grid::init(40, 40, &params);
+        let mut sim = Simulation::new(grid, params).with_seed(7);
+        for _ in 0..10 {
+            sim.step();
+        }
+        let snapshot = sim.snapshot();
+        for _ in 0..10 {
+            sim.step();
+        }
+
+        let mut restored = Simulation::restore(snapshot);
+        assert_eq!(restored.day, 10);
+        for _ in 0..10 {
+            restored.step();
+        }
+        assert_eq!(restored.day, 20);
+        assert!(restored.grid == sim.grid);
+    }
+
+    #[test]
+    // The observer fires once per day with sequential day numbers
+    fn test_simulation_run_with_case1() {
+        let params = dummy_params(0.05, 0.3, 0.0, 1.0);
+        let grid = Grid::init(20, 20, &params);
+        let mut sim = Simulation::new(grid, params).with_seed(1);
+
+        let mut days = Vec::new();
+        let steps = sim.run_with(15, |day, stats, grid| {
+            assert_eq!(stats.infected, count_states(grid).infected);
+            days.push(day);
+        });
+
+        assert_eq!(steps, 15);
+        assert_eq!(days, (1..=15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    // Without waning immunity every infection ends up infected or recovered
+    fn test_simulation_cumulative_infections_case1() {
+        let params = dummy_params(0.02, 0.8, 0.2, 1.0);
+        let grid = Grid::init(30, 30, &params);
+        let mut sim = Simulation::new(grid, params).with_seed(3);
+        for _ in 0..40 {
+            sim.step();
+        }
+        let last = sim.history.days.last().unwrap();
+        assert_eq!(sim.history.cumulative_infections, last.infected + last.recovered);
+        assert_eq!(sim.history.len(), 41);
+    }
+
+    #[test]
+    // Under SIRS cells are reinfected, so cumulative infections exceed the recovered count
+    fn test_simulation_cumulative_infections_case2() {
+        let params = SirParams { waning_rate: 0.5, ..dummy_params(0.1, 1.0, 0.5, 1.0) };
+        let grid = Grid::init(30, 30, &params);
+        let mut sim = Simulation::new(grid, params).with_seed(5);
+        for _ in 0..40 {
+            sim.step();
+        }
+        let last = sim.history.days.last().unwrap();
+        assert!(sim.history.cumulative_infections > last.recovered);
+        assert!(sim.history.cumulative_infections > last.infected + last.recovered);
+    }
+
+    #[test]
+    // With a fixed 5-step period a lone infected cell recovers on exactly the 5th step
+    fn test_simulation_infectious_period_case1() {
+        let params = SirParams {
+            infectious_period: InfectiousPeriod::Fixed(5),
+            ..dummy_params(0.0, 0.0, 0.0, 1.0)
+        };
+        let mut grid = Grid::init(3, 3, &params);
+        grid.write(grid.get_index(1, 1), HealthState::Infected);
+        let mut sim = Simulation::new(grid, params).with_seed(2);
+
+        for _ in 0..4 {
+            sim.step();
+            assert_eq!(sim.grid.read(4), HealthState::Infected);
+        }
+        sim.step();
+        assert_eq!(sim.grid.read(4), HealthState::Recovered);
+    }
+
+    #[test]
+    // Newly infected cells get their own timer when they are infected
+    fn test_simulation_infectious_period_case2() {
+        let params = SirParams {
+            infectious_period: InfectiousPeriod::Fixed(3),
+            ..dummy_params(0.0, 8.0, 0.0, 1.0)
+        };
+        let mut grid = Grid::init(2, 1, &params);
+        grid.write(0, HealthState::Infected);
+        let mut layers = CellLayers::default();
+        let mut rng = StdRng::seed_from_u64(4);
+
+        // beta * 1/8 * dt = 1, so cell 1 is infected on the first step
+        step_grid_layered(&mut grid, &mut layers, &params, &mut rng);
+        assert_eq!(grid.read(1), HealthState::Infected);
+        assert_eq!(layers.infection_timers, vec![2, 3]);
+    }
+
+    #[test]
+    // Vaccinating every susceptible in one step leaves nobody to infect
+    fn test_simulation_vaccination_case1() {
+        let params = SirParams { vaccination_rate: 1.0, ..dummy_params(0.05, 1.0, 0.0, 1.0) };
+        let mut grid = Grid::init(20, 20, &params);
+        let initial = count_states(&grid);
+
+        let new_infections = step_grid(&mut grid, &params);
+        let stats = count_states(&grid);
+        assert_eq!(new_infections, 0);
+        assert_eq!(stats.susceptible, 0);
+        assert_eq!(stats.infected, initial.infected);
+        assert_eq!(stats.recovered, initial.susceptible);
+    }
+
+    #[test]
+    // A quarantined infected cell among susceptibles infects nobody
+    fn test_simulation_quarantine_case1() {
+        let params = dummy_params(0.0, 8.0, 0.0, 1.0);
+        let mut grid = Grid::init(3, 3, &params);
+        grid.write(grid.get_index(1, 1), HealthState::Infected);
+        let mut layers = CellLayers::default();
+        layers.set_quarantined(grid.get_index(1, 1), true);
+
+        let new_infections = step_grid_layered(&mut grid, &mut layers, &params, &mut rand::thread_rng());
+        assert_eq!(new_infections, 0);
+        assert_eq!(count_states(&grid).infected, 1);
+
+        // Without quarantine beta * 1/8 * dt = 1 infects every neighbor
+        let mut layers = CellLayers::default();
+        let new_infections = step_grid_layered(&mut grid, &mut layers, &params, &mut rand::thread_rng());
+        assert_eq!(new_infections, 8);
+    }
+
+    #[test]
+    // quarantine_prob = 1 quarantines every new infection
+    fn test_simulation_quarantine_case2() {
+        let params = SirParams { quarantine_prob: 1.0, ..dummy_params(0.0, 8.0, 0.0, 1.0) };
+        let mut grid = Grid::init(3, 1, &params);
+        grid.write(0, HealthState::Infected);
+        let mut layers = CellLayers::default();
+        let mut rng = StdRng::seed_from_u64(9);
+
+        step_grid_layered(&mut grid, &mut layers, &params, &mut rng);
+        assert_eq!(grid.read(1), HealthState::Infected);
+        assert!(layers.is_quarantined(1));
+        // Cell 1 is quarantined, so cell 2 is only exposed to it and stays susceptible
+        step_grid_layered(&mut grid, &mut layers, &params, &mut rng);
+        assert_eq!(grid.read(2), HealthState::Susceptible);
+    }
+
+    #[test]
+    // Mobility only permutes cells, so the S/I/R counts are conserved
+    fn test_simulation_mobility_step_case1() {
+        let mut grid = Grid::init(30, 30, &dummy_params(0.2, 0.0, 0.0, 1.0));
+        for idx in (0..900).step_by(5) {
+            grid.write(idx, HealthState::Recovered);
+        }
+        let before = count_states(&grid);
+        let original = grid.clone();
+
+        mobility_step(&mut grid, &mut StdRng::seed_from_u64(8), 0.5);
+        assert_eq!(count_states(&grid), before);
+        assert!(grid != original);
+    }
+
+    #[test]
+    fn test_simulation_mobility_step_case2() {
+        let mut grid = Grid::init(30, 30, &dummy_params(0.2, 0.0, 0.0, 1.0));
+        let original = grid.clone();
+        mobility_step(&mut grid, &mut StdRng::seed_from_u64(8), 0.0);
+        assert!(grid == original);
+    }
+
+    #[test]
+    // One message per simulated day, and the channel closes when the run ends
+    fn test_simulation_run_streaming_case1() {
+        let params = dummy_params(0.1, 0.3, 0.2, 1.0);
+        let mut grid = Grid::init(10, 10, &params);
+        grid.write(0, HealthState::Infected);
+        let mut reference = Simulation::new(grid.clone(), params.clone()).with_seed(5);
+        let days = reference.run_with(30, |_, _, _| {});
+
+        let received: Vec<PopulationStats> = Simulation::new(grid, params).with_seed(5).run_streaming(30).iter().collect();
+        assert_eq!(received.len(), days);
+        assert_eq!(received, reference.history.days[1..]);
+    }
+
+    // Records log output per thread so parallel tests don't see each other's records
+    struct CaptureLogger;
+
+    static CAPTURED: std::sync::Mutex<Vec<(thread::ThreadId, log::Level, String)>> = std::sync::Mutex::new(Vec::new());
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            CAPTURED.lock().unwrap().push((thread::current().id(), record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    // A step emits one debug record with the new day's counts
+    fn test_simulation_step_logging_case1() {
+        static LOGGER: CaptureLogger = CaptureLogger;
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let params = dummy_params(0.0, 0.3, 0.1, 1.0);
+        let mut grid = Grid::init(4, 4, &params);
+        grid.write(0, HealthState::Infected);
+        let mut sim = Simulation::new(grid, params).with_seed(1);
+        sim.step();
+        let stats = sim.history.days[1];
+
+        let me = thread::current().id();
+        let records: Vec<_> = CAPTURED.lock().unwrap().iter().filter(|(id, _, _)| *id == me).cloned().collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].1, log::Level::Debug);
+        assert!(records[0].2.starts_with(&format!("day 1: S={} I={} R={}", stats.susceptible, stats.infected, stats.recovered)));
+    }
+
+    #[test]
+    // The parallel step conserves the population and reports its incidence
+    fn test_simulation_step_grid_parallel_case1() {
+        let params = dummy_params(0.1, 0.5, 0.1, 1.0);
+        let mut grid = Grid::init_seeded(40, 30, &params, 3);
+        let before = count_states(&grid);
+        let new_infections = step_grid_parallel(&mut grid, &params, 3, 0);
+        let after = count_states(&grid);
+        assert_eq!(after.susceptible + after.infected + after.recovered, 40 * 30);
+        assert_eq!(after.susceptible + new_infections, before.susceptible);
+    }
+
+    #[test]
+    // With reinfection_factor 1, recovered cells follow exactly the susceptible dynamics
+    fn test_simulation_reinfection_factor_case1() {
+        let params = SirParams { reinfection_factor: 1.0, ..dummy_params(0.2, 0.6, 0.1, 1.0) };
+        let susceptible_grid = Grid::init_seeded(20, 20, &params, 9);
+        let mut recovered_grid = susceptible_grid.clone();
+        for idx in 0..400 {
+            if recovered_grid.read(idx) == HealthState::Susceptible {
+                recovered_grid.write(idx, HealthState::Recovered);
+            }
+        }
+
+        let mut a = susceptible_grid;
+        let mut b = recovered_grid;
+        let infections_a = step_grid_with_rng(&mut a, &params, &mut StdRng::seed_from_u64(4));
+        let infections_b = step_grid_with_rng(&mut b, &params, &mut StdRng::seed_from_u64(4));
+        assert_eq!(infections_a, infections_b);
+        assert!(infections_a > 0);
+        for idx in 0..400 {
+            let expected = match a.read(idx) {
+                HealthState::Susceptible => HealthState::Recovered,
+                state => state,
+            };
+            assert_eq!(b.read(idx), expected, "cell {}", idx);
+        }
+    }
+
+    #[test]
+    // The default factor of 0 keeps recovered cells fully immune
+    fn test_simulation_reinfection_factor_case2() {
+        let params = dummy_params(0.0, 1.0, 0.0, 1.0);
+        let mut grid = Grid::init(3, 3, &params);
+        grid.fill(HealthState::Infected);
+        grid.write(4, HealthState::Recovered);
+        step_grid_with_rng(&mut grid, &params, &mut StdRng::seed_from_u64(0));
+        assert_eq!(grid.read(4), HealthState::Recovered);
+
+        let params = SirParams { reinfection_factor: 1.0, ..params };
+        step_grid_with_rng(&mut grid, &params, &mut StdRng::seed_from_u64(0));
+        assert_eq!(grid.read(4), HealthState::Infected);
+    }
+
+    #[test]
+    // Infection happens exactly when the neighbor-driven probability clears the threshold
+    fn test_simulation_step_grid_deterministic_case1() {
+        let params = dummy_params(0.0, 1.0, 0.0, 1.0);
+        let mut grid = Grid::init(3, 3, &params);
+        for idx in [0, 1, 2, 3, 5] {
+            grid.write(idx, HealthState::Infected);
+        }
+        // 5 of 8 neighbors infected: probability 0.625
+        let mut above = grid.clone();
+        assert_eq!(step_grid_deterministic(&mut above, &params, 0.5), 1);
+        assert_eq!(above.read(4), HealthState::Infected);
+
+        let mut below = grid.clone();
+        assert_eq!(step_grid_deterministic(&mut below, &params, 0.7), 0);
+        assert_eq!(below.read(4), HealthState::Susceptible);
+    }
+
+    #[test]
+    // Repeated runs from the same grid are identical, whatever the global RNG does
+    fn test_simulation_step_grid_deterministic_case2() {
+        let params = dummy_params(0.2, 0.9, 0.6, 1.0);
+        let start = Grid::init_seeded(15, 15, &params, 12);
+        let mut a = start.clone();
+        let mut b = start;
+        for _ in 0..10 {
+            step_grid_deterministic(&mut a, &params, 0.5);
+            let _: f64 = rand::random();
+            step_grid_deterministic(&mut b, &params, 0.5);
+            assert_eq!(a, b);
+        }
+        // gamma * dt = 0.6 > 0.5, so every infected cell recovers each step
+        assert_eq!(count_states(&a).infected, 0);
+    }
+
+    #[test]
+    // Elderly cells recover at a fifth of the rate, so more of them are still infected
+    fn test_simulation_age_structure_case1() {
+        let params = SirParams { age_gamma: [1.0, 1.0, 0.2], ..dummy_params(1.0, 0.0, 0.5, 1.0) };
+        let grid = Grid::init(20, 20, &params);
+        let mut sim = Simulation::new(grid, params).with_seed(2).with_age_structure([1.0, 0.0, 1.0]);
+        for _ in 0..3 {
+            sim.step();
+        }
+
+        let still_infected = |age: AgeBracket| {
+            let cells: Vec<usize> = (0..400).filter(|&idx| sim.layers.age_at(idx) == Some(age)).collect();
+            let infected = cells.iter().filter(|&&idx| sim.grid.read(idx) == HealthState::Infected).count();
+            infected as f64 / cells.len() as f64
+        };
+        // Expected survival after 3 steps: 0.5^3 = 0.125 for children, 0.9^3 = 0.729 for the elderly
+        let (children, elderly) = (still_infected(AgeBracket::Child), still_infected(AgeBracket::Elderly));
+        assert!(children < 0.3, "children {}", children);
+        assert!(elderly > 0.5, "elderly {}", elderly);
+    }
+
+    #[test]
+    // With enough travel the lattice is well mixed and tracks the mean-field curve;
+    // a static lattice spreads more slowly because infections cluster
+    fn test_simulation_compare_ode_to_agent_case1() {
+        let params = dummy_params(0.05, 0.4, 0.1, 1.0);
+        let mixed = compare_ode_to_agent(&SirParams { mobility: 0.5, ..params.clone() }, 60, 50);
+        let local = compare_ode_to_agent(&params, 60, 50);
+        assert!(mixed < 0.02, "mixed rmse {}", mixed);
+        assert!(local > mixed, "local rmse {} vs mixed {}", local, mixed);
+    }
+
+    #[test]
+    // A single replicate has no spread and its mean is that run's history
+    fn test_simulation_run_replicates_case1() {
+        let params = dummy_params(0.05, 0.4, 0.1, 1.0);
+        let ensemble = run_replicates(&params, 20, 20, 15, 1, 7);
+
+        let mut sim = Simulation::new(Grid::init_seeded(20, 20, &params, 7), params).with_seed(7);
+        for _ in 0..15 {
+            sim.step();
+        }
+        let infected: Vec<f64> = sim.history.days.iter().map(|stats| stats.infected as f64).collect();
+        assert_eq!(ensemble.infected.mean, infected);
+        assert_eq!(ensemble.susceptible.mean.len(), 16);
+        for series in [&ensemble.susceptible, &ensemble.infected, &ensemble.recovered] {
+            assert!(series.std_dev.iter().all(|&sd| sd == 0.0));
+        }
+    }
+
+    #[test]
+    // Different seeds give different runs, so the ensemble has spread
+    fn test_simulation_run_replicates_case2() {
+        let params = dummy_params(0.05, 0.4, 0.1, 1.0);
+        let ensemble = run_replicates(&params, 20, 20, 15, 4, 0);
+        assert!(ensemble.infected.std_dev.iter().any(|&sd| sd > 0.0));
+        for day in 0..=15 {
+            let total = ensemble.susceptible.mean[day] + ensemble.infected.mean[day] + ensemble.recovered.mean[day];
+            assert!((total - 400.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    // With certain transmission the wavefront moves one ring per day from the seed
+    fn test_simulation_infection_day_map_case1() {
+        let params = dummy_params(0.0, 8.0, 0.0, 1.0);
+        let mut grid = Grid::init(5, 5, &params);
+        grid.write(grid.get_index(2, 2), HealthState::Infected);
+        let mut sim = Simulation::new(grid, params).with_seed(1).with_infection_day_map();
+        sim.step();
+        sim.step();
+
+        let map = sim.infection_day_map();
+        assert_eq!(map[sim.grid.get_index(2, 2)], 0);
+        assert_eq!(map[sim.grid.get_index(1, 1)], 1);
+        assert_eq!(map[sim.grid.get_index(3, 2)], 1);
+        assert_eq!(map[sim.grid.get_index(0, 0)], 2);
+        assert_eq!(map[sim.grid.get_index(4, 2)], 2);
+
+        let untracked = Simulation::new(sim.grid.clone(), sim.params.clone());
+        assert!(untracked.infection_day_map().is_empty());
+    }
+
+    #[test]
+    // A deterministic run fills the grid, then freezes with everyone still infected
+    fn test_simulation_run_until_case1() {
+        // gamma * dt = 0.1 never clears the threshold, so nobody recovers
+        let params = dummy_params(0.0, 1.0, 0.1, 1.0);
+        let mut grid = Grid::init(7, 7, &params);
+        for x in 2..=4 {
+            grid.write(grid.get_index(x, 3), HealthState::Infected);
+        }
+        let mut sim = Simulation::new(grid, params).with_deterministic_threshold(0.3);
+        let (steps, reason) = sim.run_until(50, |_, _, _| {});
+        assert_eq!(reason, TerminationReason::SteadyState);
+        assert!(steps > 1 && steps < 50, "steps {}", steps);
+        assert!(sim.grid.any_infected());
+
+        // The same grid stepped stochastically never reports a steady state
+        let mut stochastic = Simulation::new(sim.grid.clone(), sim.params.clone()).with_seed(1);
+        assert_ne!(stochastic.run_until(5, |_, _, _| {}).1, TerminationReason::SteadyState);
+    }
+
+    #[test]
+    // A profile peaking on day 3 slows early spread compared to flat infectiousness
+    fn test_simulation_infectiousness_profile_case1() {
+        let run = |infectiousness: InfectiousnessProfile| {
+            let params = SirParams { infectiousness, ..dummy_params(0.0, 2.0, 0.1, 1.0) };
+            let mut grid = Grid::init(21, 21, &params);
+            grid.write(grid.get_index(10, 10), HealthState::Infected);
+            let mut sim = Simulation::new(grid, params).with_seed(11);
+            for _ in 0..6 {
+                sim.step();
+            }
+            21 * 21 - count_states(&sim.grid).susceptible
+        };
+        let flat = run(InfectiousnessProfile::Flat);
+        let delayed = run(InfectiousnessProfile::Triangular { peak: 3.0, duration: 10.0 });
+        assert!(delayed > 1, "delayed {}", delayed);
+        assert!(delayed < flat, "delayed {} flat {}", delayed, flat);
+    }
+
+    #[test]
+    // A 2x2 sweep gives one result per pair, and more transmission means a higher peak
+    fn test_simulation_sweep_case1() {
+        let results = sweep(&[0.2, 0.9], &[0.05, 0.2], 30, 30, 4);
+        assert_eq!(results.len(), 4);
+        let pairs: Vec<(f64, f64)> = results.iter().map(|result| (result.beta, result.gamma)).collect();
+        assert_eq!(pairs, [(0.2, 0.05), (0.2, 0.2), (0.9, 0.05), (0.9, 0.2)]);
+        for gamma in 0..2 {
+            let (low, high) = (&results[gamma], &results[gamma + 2]);
+            assert!(high.peak_infected > low.peak_infected, "{:?} vs {:?}", high, low);
+            assert!(high.attack_rate > low.attack_rate);
+        }
+        assert!(results.iter().all(|result| result.duration > 0 && result.attack_rate <= 1.0));
+    }
+
+    #[test]
+    // Fast-forwarding lands on the same grid as stepping day by day
+    fn test_simulation_advance_case1() {
+        let params = dummy_params(0.02, 0.4, 0.05, 1.0);
+        let grid = Grid::init_seeded(40, 40, &params, 9);
+        let mut stepped = Simulation::new(grid.clone(), params.clone()).with_seed(9);
+        for _ in 0..50 {
+            stepped.step();
+        }
+        let mut advanced = Simulation::new(grid, params).with_seed(9);
+        assert_eq!(advanced.advance(50), 50);
+
+        assert_eq!(advanced.grid, stepped.grid);
+        assert_eq!(advanced.day, 50);
+        assert_eq!(advanced.history.len(), 1);
+        assert_eq!(advanced.history.cumulative_infections, stepped.history.cumulative_infections);
+    }
+
+    #[test]
+    // Everyone recovers on the first step, so advance stops there
+    fn test_simulation_advance_case2() {
+        let params = dummy_params(0.1, 0.0, 1.0, 1.0);
+        let mut sim = Simulation::new(Grid::init_seeded(10, 10, &params, 3), params).with_seed(3);
+        assert_eq!(sim.advance(50), 1);
+        assert_eq!(sim.day, 1);
+    }
+
+    #[test]
+    // Infection crosses the grid above the threshold and dies out below it
+    fn test_simulation_find_percolation_threshold_case1() {
+        let threshold = find_percolation_threshold(30, 20, 0.3, 7, 0.01);
+        assert!(threshold > 0.0 && threshold < PERCOLATION_MAX_BETA, "threshold {}", threshold);
+        assert!(percolates(30, 20, threshold * 1.5, 0.3, 7));
+        assert!(!percolates(30, 20, threshold * 0.5, 0.3, 7));
+    }
+
+    #[test]
+    // Pinned output of seeded steps, recorded before the loops switched to a running index
+    fn test_simulation_step_running_index_case1() {
+        let params = SirParams { quarantine_prob: 0.2, ..dummy_params(0.05, 0.6, 0.1, 1.0) };
+        let mut grid = Grid::init_seeded(37, 23, &params, 12);
+        let mut rng = StdRng::seed_from_u64(12);
+        let infections: Vec<usize> = (0..5).map(|_| step_grid_with_rng(&mut grid, &params, &mut rng)).collect();
+        assert_eq!(infections, [25, 17, 31, 41, 37]);
+        assert_eq!(count_states(&grid), PopulationStats { susceptible: 659, infected: 149, recovered: 43 });
+        let checksum = |grid: &Grid| -> u64 { grid.cells.iter().enumerate().map(|(i, &b)| (i as u64 + 1) * b as u64).sum() };
+        assert_eq!(checksum(&grid), 545721);
+
+        assert_eq!(step_grid_deterministic(&mut grid, &params, 0.2), 67);
+        assert_eq!(checksum(&grid), 733585);
+    }
+
+    #[test]
+    // Starting half immune keeps the same outbreak far smaller
+    fn test_simulation_r_ratio_case1() {
+        let run = |r_ratio: f64| {
+            let params = SirParams { r_ratio, ..dummy_params(0.01, 0.5, 0.1, 1.0) };
+            let mut sim = Simulation::new(Grid::init_seeded(50, 50, &params, 21), params).with_seed(21);
+            sim.run_with(200, |_, _, _| {});
+            sim.history.cumulative_infections
+        };
+        let naive = run(0.0);
+        let immune = run(0.5);
+        assert!(naive > 1000, "naive {}", naive);
+        assert!(immune * 4 < naive, "immune {} naive {}", immune, naive);
+    }
+
+    #[test]
+    // A 5-day period reports on days 5, 10 and 15 of a 17-day run
+    fn test_simulation_with_progress_case1() {
+        use std::sync::Mutex;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let params = dummy_params(0.0, 0.0, 0.0, 1.0);
+        let mut grid = Grid::init(6, 6, &params);
+        grid.write(0, HealthState::Infected);
+        let mut sim = Simulation::new(grid, params)
+            .with_seed(1)
+            .with_progress(5, move |progress| sink.lock().unwrap().push(*progress));
+        assert_eq!(sim.run_until(17, |_, _, _| {}), (17, TerminationReason::MaxDays));
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.iter().map(|progress| progress.day).collect::<Vec<_>>(), [5, 10, 15]);
+        assert!(reports.iter().all(|progress| progress.infected == 1));
+        assert!(reports.windows(2).all(|pair| pair[0].elapsed <= pair[1].elapsed));
+    }
+
+    #[test]
+    // Per-cell seeds make the parallel step independent of the thread count
+    fn test_simulation_step_grid_parallel_case2() {
+        let params = SirParams { waning_rate: 0.05, vaccination_rate: 0.01, ..dummy_params(0.05, 0.6, 0.1, 1.0) };
+        let initial = Grid::init_seeded(64, 48, &params, 17);
+        let run_with_threads = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| {
+                let mut grid = initial.clone();
+                let infections: Vec<usize> = (0..10).map(|day| step_grid_parallel(&mut grid, &params, 99, day)).collect();
+                (grid, infections)
+            })
+        };
+        let (single, single_infections) = run_with_threads(1);
+        let (multi, multi_infections) = run_with_threads(4);
+        assert_eq!(single.cells, multi.cells);
+        assert_eq!(single_infections, multi_infections);
+
+        let mut reference = initial.clone();
+        let reference_infections: Vec<usize> = (0..10).map(|day| step_grid_seeded(&mut reference, &params, 99, day)).collect();
+        assert_eq!(reference.cells, single.cells);
+        assert_eq!(reference_infections, single_infections);
+        assert_ne!(single, initial);
+
+        // A different seed gives a different trajectory
+        let mut other = initial.clone();
+        for day in 0..10 {
+            step_grid_parallel(&mut other, &params, 100, day);
+        }
+        assert_ne!(other, single);
+    }
+
+    #[test]
+    // A tile reaching past the grid edge only steps the cells that exist
+    fn test_simulation_step_tile_case1() {
+        let params = dummy_params(1.0, 0.0, 1.0, 1.0);
+        let grid = Grid::init(10, 6, &params);
+        let mut output = grid.clone();
+        // Nominally 16x16, but only 2x3 of it lies inside the grid
+        let tile = Tile { origin_x: 8, origin_y: 3, tile_x: 16, tile_y: 16, grid: &grid };
+        step_tile(&tile, &params, 1, 0, &mut output);
+
+        for ((x, y), state) in output.iter_cells() {
+            let expected = if x >= 8 && y >= 3 { HealthState::Recovered } else { HealthState::Infected };
+            assert_eq!(state, expected, "({}, {})", x, y);
+        }
+
+        // A tile entirely off the grid is a no-op
+        let outside = Tile { origin_x: 12, origin_y: 0, tile_x: 4, tile_y: 4, grid: &grid };
+        let before = output.clone();
+        step_tile(&outside, &params, 1, 0, &mut output);
+        assert_eq!(output, before);
+    }
+
+    #[test]
+    // Continuing at a lower beta from day 10 matches the unchanged run up to day 10 only
+    fn test_simulation_from_grid_case1() {
+        let params = dummy_params(0.02, 0.4, 0.05, 1.0);
+        let mut first_leg = Simulation::new(Grid::init_seeded(40, 40, &params, 2), params.clone()).with_seed(2);
+        for _ in 0..10 {
+            first_leg.step();
+        }
+        let checkpoint = first_leg.grid.clone();
+
+        let mut constant = Simulation::from_grid(checkpoint.clone(), params.clone(), 3, 10).unwrap();
+        let mut lowered = Simulation::from_grid(checkpoint, SirParams { beta: 0.1, ..params }, 3, 10).unwrap();
+        assert_eq!(constant.day, 10);
+        for _ in 0..10 {
+            constant.step();
+            lowered.step();
+        }
+        assert_eq!(lowered.day, 20);
+
+        assert_eq!(constant.history.days[0], *first_leg.history.days.last().unwrap());
+        assert_eq!(lowered.history.days[0], constant.history.days[0]);
+        for day in 1..=10 {
+            assert_ne!(lowered.history.days[day], constant.history.days[day], "day {}", 10 + day);
+        }
+        assert!(lowered.history.cumulative_infections < constant.history.cumulative_infections);
+    }
+
+    #[test]
+    // On a small seeded grid the tiled and per-cell seeded steppers agree cell
+    // for cell at any tile size, including cells whose neighbors lie in another tile
+    fn test_simulation_next_state_case1() {
+        let params = SirParams { reinfection_factor: 0.5, waning_rate: 0.05, ..dummy_params(0.15, 0.6, 0.2, 1.0) };
+        let mut grid = Grid::init_seeded(12, 9, &params, 6);
+        grid.write(grid.get_index(7, 2), HealthState::Empty);
+        let mut changed = false;
+        for day in 0..8 {
+            let tiled = step_grid_tiled(&grid, &params, 21, day, 4, 4).unwrap();
+            assert_eq!(step_grid_tiled(&grid, &params, 21, day, 5, 2).unwrap(), tiled);
+            let before = grid.clone();
+            step_grid_seeded(&mut grid, &params, 21, day);
+            assert_eq!(tiled.debug_dump(), grid.debug_dump(), "day {}", day);
+            changed |= grid != before;
+        }
+        assert!(changed);
+    }
+
+    #[test]
+    // A 3x3 all-ones kernel gives exactly the Moore infection probability
+    fn test_simulation_infection_kernel_case1() {
+        let moore = dummy_params(0.3, 0.7, 0.1, 1.0);
+        let mut grid = Grid::init_seeded(9, 7, &moore, 4);
+        for idx in [5, 20, 33, 62] {
+            grid.write(idx, HealthState::Empty);
+        }
+        let kernel = InfectionKernel::new(3, vec![1.0; 9]).unwrap();
+        let kernelled = SirParams { kernel: Some(kernel), ..moore.clone() };
+        for ((x, y), _) in grid.iter_cells() {
+            let expected = infection_probability(&grid, x, y, &moore);
+            assert!((infection_probability(&grid, x, y, &kernelled) - expected).abs() < 1e-12, "({}, {})", x, y);
+        }
+
+        let mut a = grid.clone();
+        let mut b = grid;
+        step_grid_with_rng(&mut a, &moore, &mut StdRng::seed_from_u64(8));
+        step_grid_with_rng(&mut b, &kernelled, &mut StdRng::seed_from_u64(8));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    // A plus-shaped kernel matches von Neumann; a wider one reaches further
+    fn test_simulation_infection_kernel_case2() {
+        let mut grid = Grid::init(7, 7, &dummy_params(0.0, 1.0, 0.0, 1.0));
+        grid.write(grid.get_index(4, 4), HealthState::Infected);
+        let plus = InfectionKernel::new(3, vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0]).unwrap();
+        let von_neumann = SirParams { neighborhood: Neighborhood::VonNeumann, ..dummy_params(0.0, 1.0, 0.0, 1.0) };
+        let plus_params = SirParams { kernel: Some(plus), ..dummy_params(0.0, 1.0, 0.0, 1.0) };
+        for (x, y) in [(3, 3), (3, 4), (2, 2)] {
+            assert_eq!(infection_probability(&grid, x, y, &plus_params), infection_probability(&grid, x, y, &von_neumann));
+        }
+
+        let wide = SirParams { kernel: Some(InfectionKernel::new(5, vec![1.0; 25]).unwrap()), ..dummy_params(0.0, 1.0, 0.0, 1.0) };
+        assert!((infection_probability(&grid, 2, 2, &wide) - 1.0 / 24.0).abs() < 1e-12);
+    }
+
+    #[test]
+    // day() counts the steps taken
+    fn test_simulation_day_case1() {
+        let params = dummy_params(0.2, 0.3, 0.1, 1.0);
+        let mut sim = Simulation::new(Grid::init(10, 10, &params), params).with_seed(3);
+        assert_eq!(sim.day(), 0);
+        for _ in 0..3 {
+            sim.step();
+        }
+        assert_eq!(sim.day(), 3);
+    }
+
+    #[test]
+    // Stepping only the dirty region matches stepping every cell
+    fn test_simulation_step_grid_dirty_case1() {
+        for params in [
+            dummy_params(0.0, 0.6, 0.15, 1.0),
+            SirParams { radius: 2, reinfection_factor: 0.3, ..dummy_params(0.0, 0.4, 0.2, 1.0) },
+            SirParams { waning_rate: 0.05, ..dummy_params(0.0, 0.6, 0.15, 1.0) },
+        ] {
+            let mut full = Grid::init_with_seeds(40, 30, &params, &[(20, 15)]);
+            let mut dirty = full.clone();
+            let mut region = DirtyRegion::around_infected(&dirty, &params);
+            assert_eq!(region, Some(DirtyRegion { x0: 20 - params.radius, y0: 15 - params.radius, x1: 21 + params.radius, y1: 16 + params.radius }));
+            for day in 0..60 {
+                let expected = step_grid_seeded(&mut full, &params, 11, day);
+                assert_eq!(step_grid_dirty(&mut dirty, &params, 11, day, &mut region), expected);
+                assert_eq!(dirty, full, "day {}", day);
+                if params.waning_rate == 0.0 {
+                    assert_eq!(region, DirtyRegion::around_infected(&full, &params));
+                }
+            }
+        }
+    }
+
+    #[test]
+    // Nothing infected means nothing to step
+    fn test_simulation_step_grid_dirty_case2() {
+        let params = dummy_params(0.0, 0.6, 0.15, 1.0);
+        let mut grid = Grid::init(10, 10, &params);
+        let mut region = DirtyRegion::around_infected(&grid, &params);
+        assert_eq!(region, None);
+        assert_eq!(step_grid_dirty(&mut grid, &params, 1, 0, &mut region), 0);
+        assert_eq!(grid, Grid::init(10, 10, &params));
+    }
+
+    #[test]
+    // Width and height alone give a runnable simulation with the documented defaults
+    fn test_simulation_builder_case1() {
+        let mut sim = SimulationBuilder::new().width(30).height(20).build().unwrap();
+        assert_eq!((sim.grid.grid_x, sim.grid.grid_y), (30, 20));
+        assert_eq!(format!("{:?}", sim.params), format!("{:?}", SirParams::default()));
+        assert_eq!(sim.params.neighborhood, Neighborhood::Moore);
+        assert_eq!(sim.max_days, DEFAULT_MAX_DAYS);
+        assert_eq!(sim.day(), 0);
+        let (steps, _) = sim.run();
+        assert!((1..=DEFAULT_MAX_DAYS).contains(&steps));
+        assert_eq!(sim.day(), steps);
+    }
+
+    #[test]
+    // Seeded builds are reproducible and honour every setting
+    fn test_simulation_builder_case2() {
+        let build = || {
+            SimulationBuilder::new()
+                .width(12)
+                .height(9)
+                .params(dummy_params(0.0, 0.5, 0.2, 1.0))
+                .neighborhood(Neighborhood::VonNeumann)
+                .initial_infected(&[(0, 0), (11, 8)])
+                .seed(5)
+                .max_days(7)
+                .build()
+                .unwrap()
+        };
+        let mut a = build();
+        assert_eq!(count_states(&a.grid).infected, 2);
+        assert_eq!(a.grid.try_state_at(11, 8).unwrap(), HealthState::Infected);
+        assert_eq!(a.params.neighborhood, Neighborhood::VonNeumann);
+        let mut b = build();
+        a.run();
+        b.run();
+        assert!(a.day() <= 7);
+        assert_eq!(a.grid, b.grid);
+    }
+
+    #[test]
+    // build() reports the first problem it finds
+    fn test_simulation_builder_case3() {
+        let err = SimulationBuilder::new().width(10).build().err().unwrap();
+        assert!(matches!(err, SirError::InvalidParam { name: "height", .. }));
+        let err = SimulationBuilder::new().width(10).height(10).params(SirParams { gamma: -1.0, ..SirParams::default() }).build().err().unwrap();
+        assert!(matches!(err, SirError::InvalidParam { name: "gamma", .. }));
+        let err = SimulationBuilder::new().width(10).height(10).initial_infected(&[(10, 3)]).build().err().unwrap();
+        assert!(matches!(err, SirError::OutOfBounds { x: 10, y: 3, .. }));
+        let err = SimulationBuilder::new().width(usize::MAX).height(2).build().err().unwrap();
+        assert!(matches!(err, SirError::Grid(GridError::DimensionOverflow)));
+    }
+
+    #[test]
+    // With no vertical weight the front stays on the seed's row
+    fn test_simulation_anisotropic_kernel_case1() {
+        let kernel = InfectionKernel::anisotropic(1.0, 0.0).unwrap();
+        let params = SirParams { kernel: Some(kernel), ..dummy_params(0.0, 1.0, 0.1, 1.0) };
+        let mut grid = Grid::init_with_seeds(21, 11, &params, &[(10, 5)]);
+        for day in 0..15 {
+            step_grid_seeded(&mut grid, &params, 2, day);
+        }
+        let reached: Vec<(usize, usize)> = grid
+            .iter_cells()
+            .filter(|&(_, state)| state != HealthState::Susceptible)
+            .map(|(pos, _)| pos)
+            .collect();
+        assert!(reached.iter().all(|&(_, y)| y == 5));
+        assert!(reached.len() > 5, "front should have moved along the row: {:?}", reached);
+    }
+
+    #[test]
+    // On a line with certain transmission and a one-day infectious period the
+    // seed infects both neighbors, each later cohort one cell further out
+    fn test_simulation_effective_r_case1() {
+        let params = SirParams {
+            kernel: Some(InfectionKernel::anisotropic(1.0, 0.0).unwrap()),
+            infectious_period: InfectiousPeriod::Fixed(1),
+            ..dummy_params(0.0, 2.0, 0.0, 1.0)
+        };
+        let grid = Grid::init_with_seeds(9, 1, &params, &[(4, 0)]);
+        let mut sim = Simulation::new(grid, params).with_seed(1).with_transmission_tracking();
+        sim.run();
+        assert_eq!(sim.history.incidence, [1, 2, 2, 2, 2, 0]);
+        assert_eq!(effective_r(&sim.history)[..5], [2.0, 1.0, 1.0, 1.0, 0.0]);
+        assert!(effective_r(&sim.history)[5].is_nan());
+    }
+
+    #[test]
+    // A new infection with two infected neighbors credits each with half
+    fn test_simulation_effective_r_case2() {
+        let params = SirParams { infectious_period: InfectiousPeriod::Fixed(1), ..dummy_params(0.0, 8.0, 0.0, 1.0) };
+        let grid = Grid::init_with_seeds(3, 1, &params, &[(0, 0), (2, 0)]);
+        let mut sim = Simulation::new(grid, params).with_seed(1).with_transmission_tracking();
+        sim.step();
+        assert_eq!(sim.history.incidence, [2, 1]);
+        assert_eq!(sim.history.transmissions, [1.0]);
+        assert_eq!(effective_r(&sim.history), [0.5, 0.0]);
+    }
+
+    #[test]
+    // Frames on days 0, 5, 10, 15 and 20, plus the last day of a run cut short
+    fn test_simulation_run_collecting_case1() {
+        let params = dummy_params(0.05, 0.3, 0.0, 1.0);
+        let mut sim = Simulation::new(Grid::init_seeded(20, 20, &params, 1), params).with_seed(1);
+        let mut replay = sim.snapshot();
+        let frames = sim.run_collecting(20, 5);
+        assert_eq!(frames.len(), 5);
+        assert_eq!(sim.day(), 20);
+        assert_eq!(frames[0], replay.grid);
+        assert_eq!(frames[4], sim.grid);
+        let mut check = Simulation::restore(replay.clone());
+        check.advance(10);
+        assert_eq!(frames[2], check.grid);
+
+        replay.params.gamma = 1.0;
+        let mut dying = Simulation::restore(replay);
+        let frames = dying.run_collecting(20, 5);
+        assert_eq!(dying.day(), 1);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1], dying.grid);
+    }
+
+    #[test]
+    // A deterministic step's S->I count is exactly the drop in susceptibles
+    fn test_simulation_transitions_case1() {
+        let params = dummy_params(0.2, 0.9, 0.0, 1.0);
+        let mut grid = Grid::init_seeded(15, 15, &params, 6);
+        let before = count_states(&grid);
+        let transitions = step_grid_deterministic_transitions(&mut grid, &params, 0.2);
+        let after = count_states(&grid);
+        assert!(transitions.s_to_i > 0);
+        assert_eq!(transitions.s_to_i, before.susceptible - after.susceptible);
+        assert_eq!(transitions, TransitionCounts { s_to_i: transitions.s_to_i, ..TransitionCounts::default() });
+    }
+
+    #[test]
+    // Every compartment change is accounted for by the recorded transitions
+    fn test_simulation_transitions_case2() {
+        let params = SirParams {
+            waning_rate: 0.2,
+            vaccination_rate: 0.05,
+            reinfection_factor: 0.5,
+            ..dummy_params(0.2, 0.8, 0.3, 1.0)
+        };
+        let mut sim = Simulation::new(Grid::init_seeded(30, 30, &params, 2), params).with_seed(2);
+        for _ in 0..10 {
+            sim.step();
+        }
+        assert_eq!(sim.history.transitions.len(), sim.history.days.len());
+        assert_eq!(sim.history.transitions[0], TransitionCounts::default());
+        for (day, transitions) in sim.history.transitions.iter().enumerate().skip(1) {
+            let (before, after) = (sim.history.days[day - 1], sim.history.days[day]);
+            let s = before.susceptible + transitions.r_to_s - transitions.s_to_i - transitions.s_to_r;
+            let i = before.infected + transitions.s_to_i + transitions.r_to_i - transitions.i_to_r;
+            assert_eq!((s, i), (after.susceptible, after.infected), "day {}", day);
+            assert_eq!(transitions.new_infections(), sim.history.incidence[day]);
+        }
+    }
+
+    #[test]
+    // With a 3-day delay, day 5's reported recoveries are day 2's true ones
+    fn test_simulation_reported_stats_case1() {
+        let params = SirParams { reporting_delay: 3, ..dummy_params(0.3, 0.5, 0.3, 1.0) };
+        let mut sim = Simulation::new(Grid::init_seeded(25, 25, &params, 8), params).with_seed(8);
+        assert_eq!(sim.reported_stats(), sim.history.days[0]);
+        for _ in 0..5 {
+            sim.step();
+        }
+        let reported = sim.reported_stats();
+        let actual = sim.history.days[5];
+        assert_eq!(reported.recovered, sim.history.days[2].recovered);
+        assert!(actual.recovered > reported.recovered);
+        assert_eq!(reported.infected, actual.infected + actual.recovered - reported.recovered);
+    }
+
+    #[test]
+    // Seeded runs are reproducible and record day 0 plus every simulated day
+    fn test_simulation_run_simulation_case1() {
+        let params = dummy_params(0.05, 0.4, 0.2, 1.0);
+        let a = run_simulation(params.clone(), 30, 30, 12, 500);
+        let b = run_simulation(params.clone(), 30, 30, 12, 500);
+        assert_eq!(a.reason, TerminationReason::Extinct);
+        assert_eq!(a.history.len(), a.days + 1);
+        assert_eq!((a.days, &a.grid, &a.history.days), (b.days, &b.grid, &b.history.days));
+
+        let capped = run_simulation(SirParams { gamma: 0.0, ..params }, 30, 30, 12, 8);
+        assert_eq!((capped.days, capped.reason), (8, TerminationReason::MaxDays));
+        assert_eq!(capped.history.len(), 9);
+    }
+
+    #[test]
+    // Saturating contacts agree with linear for one infected neighbor, fall below it for
+    // more, and neither ever exceeds 1
+    fn test_simulation_contact_model_case1() {
+        let linear = dummy_params(0.0, 0.8, 0.0, 1.0);
+        let saturating = SirParams { contact_model: ContactModel::Saturating, ..linear.clone() };
+        let mut grid = Grid::init(3, 3, &linear);
+        grid.write(0, HealthState::Infected);
+        assert!((infection_probability(&grid, 1, 1, &linear) - 0.1).abs() < 1e-12);
+        assert!((infection_probability(&grid, 1, 1, &saturating) - 0.1).abs() < 1e-12);
+
+        for idx in [1, 2, 3, 5, 6, 7, 8] {
+            grid.write(idx, HealthState::Infected);
+        }
+        assert!((infection_probability(&grid, 1, 1, &linear) - 0.8).abs() < 1e-12);
+        let expected = 1.0 - 0.9f64.powi(8);
+        assert!((infection_probability(&grid, 1, 1, &saturating) - expected).abs() < 1e-12);
+        assert!(expected < 0.8);
+
+        for beta in [2.0, 9.0, 100.0] {
+            for params in [&linear, &saturating] {
+                let params = SirParams { beta, ..params.clone() };
+                for ((x, y), _) in grid.iter_cells() {
+                    let p = infection_probability(&grid, x, y, &params);
+                    assert!((0.0..=1.0).contains(&p), "beta {} gives {} at ({}, {})", beta, p, x, y);
+                }
+            }
+        }
+        assert_eq!(infection_probability(&grid, 1, 1, &SirParams { beta: 9.0, ..linear }), 1.0);
+    }
+
+    #[test]
+    // Over an ensemble the f32 path's epidemics match the f64 path's in size and timing
+    fn test_simulation_step_grid_f32_case1() {
+        let params = dummy_params(0.02, 0.6, 0.15, 1.0);
+        let replicates = 40;
+        let summarize = |step: &dyn Fn(&mut Grid, &mut StdRng)| {
+            let (mut attack, mut peak) = (0.0, 0.0);
+            for seed in 0..replicates {
+                let mut grid = Grid::init_seeded(40, 40, &params, seed);
+                let mut rng = StdRng::seed_from_u64(1000 + seed);
+                let mut max_infected = 0;
+                for _ in 0..60 {
+                    step(&mut grid, &mut rng);
+                    max_infected = max_infected.max(count_states(&grid).infected);
+                }
+                let stats = count_states(&grid);
+                attack += (stats.infected + stats.recovered) as f64 / 1600.0;
+                peak += max_infected as f64 / 1600.0;
+            }
+            (attack / replicates as f64, peak / replicates as f64)
+        };
+        let (attack_64, peak_64) = summarize(&|grid, rng| {
+            step_grid_with_rng(grid, &params, rng);
+        });
+        let (attack_32, peak_32) = summarize(&|grid, rng| {
+            step_grid_f32(grid, &params, rng);
+        });
+        assert!(attack_64 > 0.3, "epidemic should take off: {}", attack_64);
+        assert!((attack_32 - attack_64).abs() < 0.05, "attack rates {} vs {}", attack_32, attack_64);
+        assert!((peak_32 - peak_64).abs() < 0.03, "peaks {} vs {}", peak_32, peak_64);
+    }
+
+    #[test]
+    // Parameters the f32 path doesn't cover fall back to the f64 stepper
+    fn test_simulation_step_grid_f32_case2() {
+        let params = SirParams { waning_rate: 0.1, ..dummy_params(0.2, 0.5, 0.2, 1.0) };
+        let mut a = Grid::init_seeded(20, 20, &params, 3);
+        let mut b = a.clone();
+        for _ in 0..5 {
+            step_grid_f32(&mut a, &params, &mut StdRng::seed_from_u64(4));
+            step_grid_with_rng(&mut b, &params, &mut StdRng::seed_from_u64(4));
+        }
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    // With shielding each extra recovered neighbor lowers the infection probability;
+    // without it recovered neighbors are inert
+    fn test_simulation_recovered_shielding_case1() {
+        let inert = dummy_params(0.0, 0.8, 0.0, 1.0);
+        let shielded = SirParams { recovered_shielding: 1.0, ..inert.clone() };
+        let mut grid = Grid::init(3, 3, &inert);
+        grid.write(0, HealthState::Infected);
+        let base = infection_probability(&grid, 1, 1, &inert);
+        let mut previous = infection_probability(&grid, 1, 1, &shielded);
+        assert_eq!(previous, base);
+        for idx in [1, 2, 3, 5, 6, 7, 8] {
+            grid.write(idx, HealthState::Recovered);
+            let probability = infection_probability(&grid, 1, 1, &shielded);
+            assert!(probability < previous, "recovered at {} should lower {} further", idx, previous);
+            previous = probability;
+            assert_eq!(infection_probability(&grid, 1, 1, &inert), base);
+        }
+        // 1 infected among 8 neighbors, 7 of them recovered and counting twice
+        assert!((previous - 0.8 / 15.0).abs() < 1e-12);
+    }
+
+    #[test]
+    // A saved run loads back with the same seed, parameters and grid, while
+    // malformed files and invalid kernels or parameters are rejected
+    fn test_simulation_save_run_case1() {
+        let kernel = InfectionKernel::anisotropic(1.0, 0.25).unwrap();
+        let params = SirParams { kernel: Some(kernel), reporting_delay: 3, ..dummy_params(0.1, 0.4, 0.1, 1.0) };
+        let mut sim = Simulation::from_grid(Grid::init_seeded(12, 7, &params, 5), params, 99, 0).unwrap();
+        for _ in 0..4 {
+            sim.step();
+        }
+        let path = std::env::temp_dir().join(format!("sir_model_{}_run.json", std::process::id()));
+        sim.save_run(&path).unwrap();
+        let (metadata, grid) = Simulation::load_run(&path).unwrap();
+        assert_eq!(metadata.seed, Some(99));
+        assert_eq!((metadata.width, metadata.height, metadata.day), (12, 7, 4));
+        assert_eq!(metadata.crate_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(format!("{:?}", metadata.params), format!("{:?}", sim.params));
+        assert_eq!(grid, sim.grid);
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        let json = saved.replacen("\"size\": 3", "\"size\": 2", 1);
+        std::fs::write(&path, json).unwrap();
+        assert!(matches!(Simulation::load_run(&path), Err(SirError::InvalidFile(_))));
+        std::fs::write(&path, &saved[..saved.len() / 2]).unwrap();
+        assert!(matches!(Simulation::load_run(&path), Err(SirError::InvalidFile(_))));
+        let json = saved.replacen("\"gamma\": 0.1", "\"gamma\": -0.1", 1);
+        assert_ne!(json, saved);
+        std::fs::write(&path, json).unwrap();
+        assert!(matches!(Simulation::load_run(&path), Err(SirError::InvalidParam { name: "gamma", .. })));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(Simulation::new(grid, SirParams::default()).metadata().seed, None);
+    }
+
+    #[test]
+    // Streaming a run to a PPM sequence writes one valid frame per day plus the start
+    fn test_simulation_run_into_case1() {
+        let params = dummy_params(0.05, 0.3, 0.2, 1.0);
+        let mut sim = Simulation::new(Grid::init_seeded(9, 6, &params, 2), params).with_seed(8);
+        let dir = std::env::temp_dir().join(format!("sir_model_{}_run_frames", std::process::id()));
+        let mut sink = PpmSequence::new(&dir, Palette::default()).unwrap();
+        let (steps, _) = sim.run_into(12, &mut sink).unwrap();
+        assert_eq!(sink.frames(), steps + 1);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), steps + 1);
+        for n in 1..=steps + 1 {
+            let bytes = std::fs::read(sink.frame_path(n)).unwrap();
+            let header = b"P6\n9 6\n255\n";
+            assert_eq!(&bytes[..header.len()], header);
+            assert_eq!(bytes.len(), header.len() + 9 * 6 * 3);
+        }
+        let mut last = Vec::new();
+        write_ppm(&mut last, &sim.grid, &Palette::default()).unwrap();
+        assert_eq!(std::fs::read(sink.frame_path(steps + 1)).unwrap(), last);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    // With a crowding penalty an infected cell ringed by infected neighbors
+    // recovers less often than an isolated one under the same gamma
+    fn test_simulation_crowding_penalty_case1() {
+        let params = SirParams { crowding_penalty: 0.8, neighborhood: Neighborhood::Moore, ..dummy_params(0.0, 0.0, 0.5, 1.0) };
+        let mut isolated = Grid::init(3, 3, &params);
+        isolated.write(4, HealthState::Infected);
+        let mut crowded = isolated.clone();
+        crowded.fill(HealthState::Infected);
+        let layers = CellLayers::default();
+        let recoveries = |grid: &Grid, params: &SirParams| {
+            let ctx = CellContext { grid, layers: &layers, params, x: 1, y: 1, idx: 4 };
+            let mut rng = StdRng::seed_from_u64(3);
+            (0..4000).filter(|_| next_state(HealthState::Infected, &ctx, &mut rng) == HealthState::Recovered).count()
+        };
+        let alone = recoveries(&isolated, &params);
+        let packed = recoveries(&crowded, &params);
+        assert!((1800..2200).contains(&alone), "isolated recovered {} of 4000", alone);
+        // gamma * (1 - 0.8) = 0.1
+        assert!((300..500).contains(&packed), "crowded recovered {} of 4000", packed);
+        assert_eq!(recoveries(&crowded, &SirParams { crowding_penalty: 0.0, ..params.clone() }), alone);
+
+        // Overwhelmed cells recover at the floor rate, slower still but not never
+        let overwhelmed = SirParams { crowding_penalty: 3.0, ..params.clone() };
+        assert_eq!(overwhelmed.crowding_factor(1.0), MIN_CROWDING_FACTOR);
+        let stalled = recoveries(&crowded, &overwhelmed);
+        assert!(stalled > 0 && stalled < packed, "overwhelmed recovered {} of 4000", stalled);
+        assert!(SirParams { crowding_penalty: -0.1, ..params }.validate().is_err());
+    }
+
+    #[test]
+    // The memory estimate matches the buffer a grid of that size really allocates,
+    // and the time estimate scales with cells and days
+    fn test_simulation_estimate_run_cost_case1() {
+        let estimate = estimate_run_cost(301, 77, 40).unwrap();
+        let grid = Grid::init(301, 77, &SirParams::default());
+        assert_eq!(estimate.memory, grid.memory_usage());
+        assert_eq!(estimate.memory.heap_bytes, grid.cells.capacity());
+        assert_eq!(estimate.peak_heap_bytes, 2 * grid.cells.len());
+        assert!(estimate.per_day > Duration::ZERO);
+        assert_eq!(estimate.total, estimate.per_day.mul_f64(40.0));
+        assert!(matches!(estimate_run_cost(usize::MAX, 2, 1), Err(SirError::Grid(GridError::DimensionOverflow))));
+        assert_eq!(estimate_run_cost(40_000, 25_000, 100).unwrap().memory.heap_bytes, 250_000_000);
+    }
+
+    #[test]
+    // A zero tile dimension is reported instead of panicking
+    fn test_simulation_step_grid_tiled_case1() {
+        let params = dummy_params(0.2, 0.3, 0.1, 1.0);
+        let grid = Grid::init_seeded(6, 4, &params, 1);
+        let err = step_grid_tiled(&grid, &params, 1, 0, 0, 2).unwrap_err();
+        assert!(matches!(err, SirError::Grid(GridError::InvalidTileSize { width: 0, height: 2 })));
+        assert!(step_grid_tiled_auto(&grid, &params, 1, 0).is_ok());
+    }
+
+    #[test]
+    // A run continued from day 10 stamps infection days from day 10, keeps
+    // transmissions aligned with its restarted history, and rejects bad params
+    fn test_simulation_from_grid_case2() {
+        let params = dummy_params(0.05, 0.5, 0.1, 1.0);
+        let grid = Grid::init_seeded(20, 20, &params, 4);
+        let infected: Vec<usize> = (0..400).filter(|&idx| grid.read(idx) == HealthState::Infected).collect();
+        let mut sim = Simulation::from_grid(grid, params.clone(), 5, 10)
+            .unwrap()
+            .with_infection_day_map()
+            .with_transmission_tracking();
+        for _ in 0..6 {
+            sim.step();
+        }
+        assert_eq!(sim.day(), 16);
+        let map = sim.infection_day_map();
+        assert!(infected.iter().all(|&idx| map[idx] == 10));
+        assert!(map.iter().all(|&day| day == NEVER_INFECTED || (10..=16).contains(&day)));
+        assert!(map.iter().any(|&day| day > 10));
+
+        assert_eq!(sim.history.days.len(), 7);
+        assert!(sim.history.transmissions.len() <= sim.history.incidence.len());
+        // Every cohort's secondary infections add up to every infection after the start
+        let attributed: f64 = sim.history.transmissions.iter().sum();
+        let caused: usize = sim.history.incidence[1..].iter().sum();
+        assert!((attributed - caused as f64).abs() < 1e-9);
+        assert!(sim.history.transmissions[0] > 0.0);
+
+        let bad = SirParams { gamma: f64::NAN, ..params };
+        assert!(matches!(
+            Simulation::from_grid(Grid::init(4, 4, &bad), bad, 1, 3),
+            Err(SirError::InvalidParam { name: "gamma", .. })
+        ));
+    }
+}
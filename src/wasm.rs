@@ -0,0 +1,65 @@
+//! Browser bindings, built with wasm-pack when the `wasm-bindgen` feature is
+//! enabled. Everything is seeded since `thread_rng` has no entropy on wasm.
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use wasm_bindgen::prelude::*;
+
+use crate::utils::grid::Grid;
+use crate::utils::maths::SirParams;
+use crate::utils::render::{self, Palette};
+use crate::utils::simulation::Simulation;
+
+/// A seeded simulation driven from JavaScript.
+#[wasm_bindgen]
+pub struct WasmSim {
+    sim: Simulation,
+    palette: Palette,
+}
+
+#[wasm_bindgen]
+impl WasmSim {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize, beta: f64, gamma: f64, dt: f64, i_ratio: f64, seed: u64) -> Result<WasmSim, JsError> {
+        let params = SirParams { beta, gamma, dt, i_ratio, ..SirParams::default() };
+        let grid = Grid::try_init_with_rng(width, height, &params, &mut StdRng::seed_from_u64(seed))?;
+        let sim = Simulation::try_new(grid, params).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(WasmSim { sim: sim.with_seed(seed), palette: Palette::default() })
+    }
+
+    /// Switch between the default and the colorblind-safe palette.
+    pub fn set_colorblind_palette(&mut self, enabled: bool) {
+        self.palette = if enabled { Palette::colorblind_safe() } else { Palette::default() };
+    }
+
+    /// Advance one day.
+    pub fn step(&mut self) {
+        self.sim.step();
+    }
+
+    /// Flat RGBA buffer, row-major, ready for `ImageData` / `putImageData`.
+    pub fn render_rgba(&self) -> Vec<u8> {
+        render::render_rgba(&self.sim.grid, &self.palette)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::grid::HealthState;
+
+    #[test]
+    fn test_wasm_render_rgba_case1() {
+        let mut sim = WasmSim::new(8, 5, 0.3, 0.1, 1.0, 0.2, 7).unwrap();
+        let pixels = sim.render_rgba();
+        assert_eq!(pixels.len(), 8 * 5 * 4);
+        for (i, ((_, state), px)) in sim.sim.grid.iter_cells().zip(pixels.chunks(4)).enumerate() {
+            let [r, g, b] = Palette::default().color(state);
+            assert_eq!(px, [r, g, b, 255], "pixel {}", i);
+        }
+
+        sim.set_colorblind_palette(true);
+        let infected = sim.sim.grid.iter_cells().position(|(_, state)| state == HealthState::Infected).unwrap();
+        let [r, g, b] = Palette::colorblind_safe().infected;
+        assert_eq!(sim.render_rgba()[infected * 4..infected * 4 + 4], [r, g, b, 255]);
+    }
+}